@@ -0,0 +1,45 @@
+use clap::Parser;
+use commands::filter::{parse_repo_filter, FilterTerm, RepoFilter};
+use commands::orgs::{require_role, Role};
+use commands::{get_repo_home, is_archived, set_repo_option};
+use std::process;
+
+/// Make repositories read-only
+#[derive(Parser)]
+#[command(about, arg_required_else_help = true)]
+struct Cli {
+    /// Paths to repositories, optionally narrowed by filters
+    #[arg(required = true, num_args = 1.., value_parser = clap::builder::ValueParser::new(parse_repo_filter))]
+    path: Vec<FilterTerm>,
+}
+
+fn main() {
+    let args = Cli::parse();
+
+    let paths = RepoFilter::new(&args.path).repos();
+    if paths.is_empty() {
+        eprintln!("No matching repositories found");
+        process::exit(1);
+    }
+
+    let repo_home = get_repo_home();
+    let mut archived = 0;
+    for path in &paths {
+        let git_dir = repo_home.join(path);
+        if let Err(err) = require_role(path, Role::Owner) {
+            eprintln!("'{}' : {}", path.display(), err);
+            continue;
+        }
+        if is_archived(&git_dir) {
+            println!("'{}' is already archived", path.display());
+            continue;
+        }
+        set_repo_option(&git_dir, "archived", "true").expect("Failed to archive repository");
+        println!("Archived '{}'", path.display());
+        archived += 1;
+    }
+    match archived {
+        1 => println!("Archived 1 repository"),
+        _ => println!("Archived {} repositories", archived),
+    }
+}
@@ -0,0 +1,132 @@
+use clap::{Parser, Subcommand};
+use commands::config::config;
+use commands::hooks::{check_update, post_receive, RefUpdate, NULL_SHA};
+use commands::orgs::{require_role, Role};
+use commands::{default_branch, is_archived, parse_branch, parse_repo_path, repo_git_dir, run_git};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Delete or rename a branch of a repository
+#[derive(Parser)]
+#[command(about, arg_required_else_help = true)]
+struct Cli {
+    #[command(subcommand)]
+    action: Action,
+}
+
+#[derive(Subcommand)]
+enum Action {
+    /// Delete a branch
+    Delete {
+        /// Path to repository
+        #[arg(value_parser = clap::builder::ValueParser::new(parse_repo_path))]
+        path: PathBuf,
+        /// Branch to delete
+        #[arg(value_parser = clap::builder::ValueParser::new(parse_branch))]
+        name: String,
+    },
+    /// Rename a branch, updating the default branch if needed
+    Rename {
+        /// Path to repository
+        #[arg(value_parser = clap::builder::ValueParser::new(parse_repo_path))]
+        path: PathBuf,
+        /// Branch to rename
+        #[arg(value_parser = clap::builder::ValueParser::new(parse_branch))]
+        name: String,
+        /// New name of the branch
+        #[arg(value_parser = clap::builder::ValueParser::new(parse_branch))]
+        new_name: String,
+    },
+}
+
+fn main() {
+    let args = Cli::parse();
+
+    let (path, name) = match &args.action {
+        Action::Delete { path, name } => (path, name),
+        Action::Rename { path, name, .. } => (path, name),
+    };
+    let git_dir = repo_git_dir(path).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+    if let Err(err) = require_role(path, Role::Write).and_then(|_| check_branch(&git_dir, name)) {
+        eprintln!("'{}' : {}", path.display(), err);
+        process::exit(1);
+    }
+
+    let commit = run_git(&git_dir, &["rev-parse", &format!("refs/heads/{}", name)])
+        .map(|commit| commit.trim().to_string())
+        .unwrap_or_else(|err| {
+            eprintln!("'{}' : {}", path.display(), err);
+            process::exit(1);
+        });
+    let mut updates = vec![RefUpdate {
+        old: commit.clone(),
+        new: String::from(NULL_SHA),
+        name: format!("refs/heads/{}", name),
+    }];
+    if let Action::Rename { new_name, .. } = &args.action {
+        updates.push(RefUpdate {
+            old: String::from(NULL_SHA),
+            new: commit,
+            name: format!("refs/heads/{}", new_name),
+        });
+    }
+    // Moving branches directly skips the hooks, so check them like a push, including the
+    // required statuses of a protected new name
+    for update in &updates {
+        if let Err(problems) = check_update(&git_dir, update, false) {
+            eprintln!("'{}' : {}", path.display(), problems.join("\n"));
+            process::exit(1);
+        }
+    }
+
+    match &args.action {
+        Action::Delete { .. } => {
+            if default_branch(&git_dir).as_ref() == Some(name) {
+                eprintln!(
+                    "'{}' : Cannot delete '{}', it is the default branch",
+                    path.display(),
+                    name
+                );
+                process::exit(1);
+            }
+            run_git(&git_dir, &["branch", "--delete", "--force", name]).unwrap_or_else(|err| {
+                eprintln!("'{}' : {}", path.display(), err);
+                process::exit(1);
+            });
+            println!("Deleted branch '{}' of '{}'", name, path.display());
+        }
+        Action::Rename { new_name, .. } => {
+            run_git(&git_dir, &["branch", "--move", name, new_name]).unwrap_or_else(|err| {
+                eprintln!("'{}' : {}", path.display(), err);
+                process::exit(1);
+            });
+            println!(
+                "Renamed branch '{}' -> '{}' of '{}'",
+                name,
+                new_name,
+                path.display()
+            );
+        }
+    }
+    post_receive(&git_dir, &updates);
+}
+
+/// Whether an existing branch may be changed
+fn check_branch(git_dir: &Path, name: &str) -> Result<(), String> {
+    if is_archived(git_dir) {
+        return Err(String::from(
+            "Repository is archived and read-only, unarchive it to change branches",
+        ));
+    }
+    let reference = format!("refs/heads/{}", name);
+    if run_git(git_dir, &["show-ref", "--verify", "--quiet", &reference]).is_err() {
+        return Err(format!("No branch named '{}'", name));
+    }
+    if config().protection.is_protected(name) {
+        return Err(format!("'{}' is protected", name));
+    }
+    Ok(())
+}
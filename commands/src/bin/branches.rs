@@ -0,0 +1,94 @@
+use clap::Parser;
+use commands::config::config;
+use commands::status::{combined_state, statuses};
+use commands::{default_branch, parse_repo_path, repo_git_dir, run_git};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// List the branches of a repository
+#[derive(Parser)]
+#[command(about, arg_required_else_help = true)]
+struct Cli {
+    /// Path to repository
+    #[arg(value_parser = clap::builder::ValueParser::new(parse_repo_path))]
+    path: PathBuf,
+}
+
+fn main() {
+    let args = Cli::parse();
+
+    let git_dir = repo_git_dir(&args.path).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+
+    let branches = run_git(
+        &git_dir,
+        &[
+            "for-each-ref",
+            "--sort=-committerdate",
+            "--format=%(refname:short)%00%(objectname)%00%(committerdate:short)%00%(subject)",
+            "refs/heads/",
+        ],
+    )
+    .unwrap_or_else(|err| {
+        eprintln!("'{}' : {}", args.path.display(), err);
+        process::exit(1);
+    });
+
+    if branches.trim().is_empty() {
+        println!("'{}' has no branches", args.path.display());
+        return;
+    }
+
+    let default = default_branch(&git_dir);
+    let protection = &config().protection;
+    println!(
+        "  {:<24} {:<9} {:<10} {:<6} {:<6} {:<8} SUBJECT",
+        "BRANCH", "COMMIT", "UPDATED", "AHEAD", "BEHIND", "STATUS"
+    );
+    for line in branches.lines() {
+        let fields = line.split('\0').collect::<Vec<_>>();
+        let [name, commit, date, subject] = fields[..] else {
+            continue;
+        };
+        let is_default = default.as_deref() == Some(name);
+        let (ahead, behind) = match &default {
+            Some(default) if !is_default => ahead_behind(&git_dir, default, name),
+            _ => (String::from("-"), String::from("-")),
+        };
+        let status = match combined_state(&statuses(&git_dir, commit)) {
+            Some(state) => state.name(),
+            None => "-",
+        };
+        let marker = if is_default { "*" } else { " " };
+        let protected = match protection.is_protected(name) {
+            true => " (protected)",
+            false => "",
+        };
+        println!(
+            "{} {:<24} {:<9.7} {:<10} {:<6} {:<6} {:<8} {}{}",
+            marker, name, commit, date, ahead, behind, status, subject, protected
+        );
+    }
+}
+
+/// Commits a branch has that the default branch lacks, and the other way around
+fn ahead_behind(git_dir: &Path, default: &str, branch: &str) -> (String, String) {
+    let range = format!("refs/heads/{}...refs/heads/{}", default, branch);
+    let counts = run_git(
+        git_dir,
+        &[
+            "rev-list",
+            "--left-right",
+            "--count",
+            "--end-of-options",
+            &range,
+        ],
+    )
+    .unwrap_or_default();
+    match counts.split_once('\t') {
+        Some((behind, ahead)) => (ahead.trim().to_string(), behind.trim().to_string()),
+        None => (String::from("?"), String::from("?")),
+    }
+}
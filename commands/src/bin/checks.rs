@@ -0,0 +1,74 @@
+use clap::Parser;
+use commands::filter::{parse_repo_filter, FilterTerm, RepoFilter};
+use commands::hooks::{enabled_checks, parse_check, prepare_check};
+use commands::orgs::{require_role, Role};
+use commands::{get_repo_home, is_archived, run_git, REPO_OPTION_SECTION};
+use std::process;
+
+/// List or change the optional checks run on pushes to repositories
+#[derive(Parser)]
+#[command(about, arg_required_else_help = true)]
+struct Cli {
+    /// Paths to repositories, optionally narrowed by filters
+    #[arg(required = true, num_args = 1.., value_parser = clap::builder::ValueParser::new(parse_repo_filter))]
+    path: Vec<FilterTerm>,
+    /// Checks to enable
+    #[arg(short, long, num_args = 1.., value_parser = clap::builder::ValueParser::new(parse_check))]
+    enable: Vec<String>,
+    /// Checks to disable
+    #[arg(short, long, num_args = 1.., value_parser = clap::builder::ValueParser::new(parse_check))]
+    disable: Vec<String>,
+}
+
+fn main() {
+    let args = Cli::parse();
+
+    let paths = RepoFilter::new(&args.path).repos();
+    if paths.is_empty() {
+        eprintln!("No matching repositories found");
+        process::exit(1);
+    }
+
+    let changing = !args.enable.is_empty() || !args.disable.is_empty();
+    let repo_home = get_repo_home();
+    let key = format!("{}.check", REPO_OPTION_SECTION);
+    for path in &paths {
+        let git_dir = repo_home.join(path);
+        if changing {
+            if let Err(err) = require_role(path, Role::Owner) {
+                eprintln!("'{}' : {}", path.display(), err);
+                continue;
+            }
+        }
+        let mut checks = enabled_checks(&git_dir);
+
+        if changing && is_archived(&git_dir) {
+            eprintln!(
+                "'{}' : Repository is archived and read-only, unarchive it to change checks",
+                path.display()
+            );
+            continue;
+        }
+        for check in &args.disable {
+            if checks.contains(check) {
+                let pattern = format!("^{}$", check);
+                run_git(&git_dir, &["config", "--unset-all", &key, &pattern])
+                    .expect("Failed to disable check");
+                checks.retain(|c| c != check);
+            }
+        }
+        for check in &args.enable {
+            if !checks.contains(check) {
+                run_git(&git_dir, &["config", "--add", &key, check])
+                    .and_then(|_| prepare_check(&git_dir, check))
+                    .expect("Failed to enable check");
+                checks.push(check.clone());
+            }
+        }
+
+        match checks.is_empty() {
+            true => println!("'{}' has no checks enabled", path.display()),
+            false => println!("'{}' : {}", path.display(), checks.join(", ")),
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use clap::{Parser, Subcommand};
+use commands::ci::{claim_job, jobs, load_job, print_log, run_job, Job, JobState};
+use commands::config::Config;
+use commands::orgs::repo_org;
+use commands::stats::format_age;
+use commands::{all_bare_repos, is_server, parse_repo_path, parse_revision, repo_git_dir, run_git};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Show the results of jobs run for pushed commits
+#[derive(Parser)]
+#[command(about, arg_required_else_help = true)]
+struct Cli {
+    #[command(subcommand)]
+    action: Option<Action>,
+    /// Run queued jobs of every repository on the server
+    #[arg(long, hide = true)]
+    scheduled: bool,
+}
+
+#[derive(Subcommand)]
+enum Action {
+    /// List recent jobs, or the job of a single revision
+    Status {
+        /// Path to repository
+        #[arg(value_parser = clap::builder::ValueParser::new(parse_repo_path))]
+        path: PathBuf,
+        /// Revision whose job to show
+        #[arg(value_parser = clap::builder::ValueParser::new(parse_revision))]
+        revision: Option<String>,
+        /// Number of jobs to list
+        #[arg(short = 'n', long, default_value_t = 20)]
+        count: usize,
+    },
+    /// Show the output of a job, the most recent one if no revision is given
+    Logs {
+        /// Path to repository
+        #[arg(value_parser = clap::builder::ValueParser::new(parse_repo_path))]
+        path: PathBuf,
+        /// Revision whose job to show
+        #[arg(value_parser = clap::builder::ValueParser::new(parse_revision))]
+        revision: Option<String>,
+    },
+}
+
+fn main() {
+    let args = Cli::parse();
+
+    if args.scheduled {
+        if !is_server() {
+            eprintln!("Scheduled jobs can only be run by the server");
+            process::exit(1);
+        }
+        run_scheduled();
+        return;
+    }
+
+    let Some(action) = args.action else {
+        return;
+    };
+    let (path, revision) = match &action {
+        Action::Status { path, revision, .. } | Action::Logs { path, revision } => (path, revision),
+    };
+    let git_dir = repo_git_dir(path).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+    let job = revision.as_ref().map(|revision| {
+        find_job(&git_dir, revision).unwrap_or_else(|err| {
+            eprintln!("'{}' : {}", path.display(), err);
+            process::exit(1);
+        })
+    });
+
+    match action {
+        Action::Status { count, .. } => {
+            let jobs = match job {
+                Some(job) => vec![job],
+                None => jobs(&git_dir).into_iter().take(count).collect(),
+            };
+            if jobs.is_empty() {
+                println!("'{}' has no jobs", path.display());
+                return;
+            }
+            println!("{:<9} {:<24} {:<8} QUEUED", "COMMIT", "BRANCH", "STATE");
+            for job in &jobs {
+                let duration = match job.duration() {
+                    Some(duration) => format!(", took {}s", duration),
+                    None => String::new(),
+                };
+                println!(
+                    "{:<9.7} {:<24} {:<8} {}{}",
+                    job.commit,
+                    job.branch,
+                    job.state.name(),
+                    format_age(job.queued),
+                    duration
+                );
+            }
+        }
+        Action::Logs { .. } => {
+            let Some(job) = job.or_else(|| jobs(&git_dir).into_iter().next()) else {
+                println!("'{}' has no jobs", path.display());
+                return;
+            };
+            if let Err(err) = print_log(&git_dir, &job.commit) {
+                eprintln!("'{}' : {}", path.display(), err);
+                process::exit(1);
+            }
+        }
+    }
+}
+
+fn find_job(git_dir: &Path, revision: &str) -> Result<Job, String> {
+    let commit = format!("{}^{{commit}}", revision);
+    let commit = run_git(
+        git_dir,
+        &[
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            "--end-of-options",
+            &commit,
+        ],
+    )
+    .map_err(|_| format!("No commit '{}'", revision))?;
+    load_job(git_dir, commit.trim()).ok_or(format!("No job for '{}'", revision))
+}
+
+/// Run every queued job with the CI settings of the repository's owner
+fn run_scheduled() {
+    for (path, git_dir) in all_bare_repos() {
+        let mut queued = jobs(&git_dir)
+            .into_iter()
+            .filter(|job| job.state == JobState::Queued)
+            .collect::<Vec<_>>();
+        if queued.is_empty() {
+            continue;
+        }
+        queued.reverse();
+        let owner = path.components().next().unwrap().as_os_str();
+        let settings = match Config::load(&owner.to_string_lossy()) {
+            Ok(config) if config.ci.enabled => config.ci,
+            Ok(_) => continue,
+            Err(err) => {
+                eprintln!("'{}' : {}", path.display(), err);
+                continue;
+            }
+        };
+        // Organisation repositories are named with their organisation, like their members do
+        let repository = match repo_org(&path) {
+            Some(_) => path.clone(),
+            None => path.iter().skip(1).collect(),
+        };
+
+        for job in queued {
+            let commit = job.commit.clone();
+            if !claim_job(&git_dir, &commit) {
+                continue;
+            }
+            match run_job(&git_dir, &repository, job, &settings) {
+                Ok(state) => println!("'{}' : {:.7} {}", path.display(), commit, state.name()),
+                Err(err) => eprintln!(
+                    "'{}' : Job for {:.7} failed: {}",
+                    path.display(),
+                    commit,
+                    err
+                ),
+            }
+        }
+    }
+}
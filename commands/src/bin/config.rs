@@ -0,0 +1,29 @@
+use clap::Parser;
+use commands::config::{config, config_path};
+use commands::get_username;
+
+/// Show the server configuration in effect
+#[derive(Parser)]
+#[command(about)]
+struct Cli {}
+
+fn main() {
+    Cli::parse();
+
+    let config = config();
+    let path = config_path();
+    let source = if path.exists() {
+        format!("from '{}'", path.display())
+    } else {
+        String::from("defaults")
+    };
+    println!("Configuration for '{}' ({}):", get_username(), source);
+
+    let rendered = toml::to_string(config).expect("Failed to render configuration");
+    for line in rendered.lines() {
+        match line {
+            "" => println!(),
+            _ => println!("  {}", line),
+        }
+    }
+}
@@ -1,10 +1,12 @@
 use clap::Parser;
-use commands::{get_repo_home, parse_repo_path};
+use commands::orgs::{repo_org, require_role, sync_links, Role};
+use commands::webhooks::{notify, RepoEvent};
+use commands::{get_repo_home, get_username, parse_repo_path};
 use itertools::Itertools;
 use std::collections::HashSet;
-use std::fmt::Debug;
 use std::os::unix;
-use std::path::PathBuf;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::{fs, process};
 
@@ -21,9 +23,22 @@ fn main() {
     let args = Cli::parse();
 
     let paths = args.path.iter().collect::<HashSet<_>>();
+    for path in &paths {
+        if let Err(err) = require_role(path, Role::Write) {
+            eprintln!("'{}' : {}", path.display(), err);
+            process::exit(1);
+        }
+    }
+    if paths.iter().any(|path| repo_org(path).is_some()) {
+        // Links to organisations are kept up to date by the server, make sure ours exist now
+        sync_links(&get_username()).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        });
+    }
     let existing = paths.iter().filter(|p| p.exists()).collect::<Vec<_>>();
 
-    if existing.len() > 0 {
+    if !existing.is_empty() {
         match existing.len() {
             1 => eprintln!("Repo already exists at '{}'", existing[0].display()),
             _ => eprintln!(
@@ -39,7 +54,13 @@ fn main() {
 
     let git_home = get_repo_home();
     for path in &paths {
-        let git_dir = git_home.join(&path);
+        let git_dir = git_home.join(path);
+        if repo_org(path).is_some() {
+            create_shared(&git_dir);
+            println!("Created '{}'", path.display());
+            notify(path, RepoEvent::Create);
+            continue;
+        }
         fs::create_dir_all(&git_dir).expect("Failed to create folders");
 
         if let Some(folder) = path.parent() {
@@ -52,10 +73,31 @@ fn main() {
             .output()
             .expect("Failed to create repo");
 
-        unix::fs::symlink(git_dir, &path).expect("Failed to link repo");
+        unix::fs::symlink(git_dir, path).expect("Failed to link repo");
         println!("Created '{}'", path.display());
+        notify(path, RepoEvent::Create);
     }
     if paths.len() > 1 {
         println!("Created {} new repositories", paths.len());
     }
 }
+
+/// Create a repository of an organisation, writable by all its members
+fn create_shared(git_dir: &Path) {
+    let created = git_dir
+        .ancestors()
+        .take_while(|folder| !folder.exists())
+        .map(Path::to_path_buf)
+        .collect::<Vec<_>>();
+    fs::create_dir_all(git_dir).expect("Failed to create folders");
+    for folder in &created {
+        fs::set_permissions(folder, fs::Permissions::from_mode(0o2770))
+            .expect("Failed to share folder");
+    }
+
+    Command::new("git")
+        .args(["init", "--bare", "--shared=group"])
+        .arg(git_dir)
+        .output()
+        .expect("Failed to create repo");
+}
@@ -1,9 +1,8 @@
 use clap::Parser;
-use commands::{
-    clean_empty_parent_folders, filter_repos, get_repo_home, make_glob_set, parse_repo_glob,
-    represents_repo,
-};
-use globset::Glob;
+use commands::filter::{parse_repo_filter, FilterTerm, RepoFilter};
+use commands::orgs::{org_folder_name, repo_org, require_role, Role};
+use commands::webhooks::{notify, RepoEvent};
+use commands::{clean_empty_parent_folders, get_repo_home};
 use std::io::Write;
 use std::{fs, io};
 
@@ -11,9 +10,9 @@ use std::{fs, io};
 #[derive(Parser)]
 #[command(about, arg_required_else_help = true)]
 struct Cli {
-    /// Paths to repositories
-    #[arg(required = true, num_args = 1.., value_parser = clap::builder::ValueParser::new(parse_repo_glob))]
-    path: Vec<Glob>,
+    /// Paths to repositories, optionally narrowed by filters such as `!dir/keep-*` or `is:empty`
+    #[arg(required = true, num_args = 1.., value_parser = clap::builder::ValueParser::new(parse_repo_filter))]
+    path: Vec<FilterTerm>,
     /// List changes without performing them
     #[arg(group = "dry", short, long)]
     dry_run: bool,
@@ -25,17 +24,12 @@ struct Cli {
 fn main() {
     let args = Cli::parse();
 
-    let glob_set = make_glob_set(args.path.iter());
-    let paths = filter_repos(None, true, |path| glob_set.is_match(path))
-        .map(|path| {
-            if represents_repo(&path) {
-                vec![path]
-            } else {
-                filter_repos(Some(path), false, |_| true).collect::<Vec<_>>()
-            }
-        })
-        .flatten()
-        .collect::<Vec<_>>();
+    let filter = RepoFilter::new(&args.path);
+    if !filter.has_includes() {
+        eprintln!("At least one path to delete is required");
+        std::process::exit(1);
+    }
+    let paths = filter.repos();
 
     if paths.is_empty() {
         eprintln!("No matching repositories found");
@@ -52,6 +46,10 @@ fn main() {
 
     let mut deleted = 0;
     for path in &paths {
+        if let Err(err) = require_role(path, Role::Owner) {
+            eprintln!("'{}' : {}", path.display(), err);
+            continue;
+        }
         if !args.confirm {
             let stdin = io::stdin();
             let input = &mut String::new();
@@ -68,14 +66,22 @@ fn main() {
         }
 
         let repo_home = get_repo_home();
-        let git_dir = repo_home.join(&path);
-        fs::remove_file(&path).expect("Failed to unlink repo");
-        fs::remove_dir_all(&git_dir).expect("Failed to remove repo");
-
-        clean_empty_parent_folders(&path, None);
-        clean_empty_parent_folders(&git_dir, Some(&repo_home));
+        let git_dir = repo_home.join(path);
+        match repo_org(path) {
+            Some(org) => {
+                fs::remove_dir_all(&git_dir).expect("Failed to remove repo");
+                clean_empty_parent_folders(&git_dir, Some(&repo_home.join(org_folder_name(&org))));
+            }
+            None => {
+                fs::remove_file(path).expect("Failed to unlink repo");
+                fs::remove_dir_all(&git_dir).expect("Failed to remove repo");
+                clean_empty_parent_folders(path, None);
+                clean_empty_parent_folders(&git_dir, Some(&repo_home));
+            }
+        }
 
         println!("Deleted '{}'", path.display());
+        notify(path, RepoEvent::Delete);
         deleted += 1;
     }
     match deleted {
@@ -0,0 +1,271 @@
+use clap::Parser;
+use commands::config::config;
+use commands::email::{self, WATCHERS_FILE};
+use commands::orgs::{all_orgs, org_dir, CHANGES_FILE, MEMBERS_FILE};
+use commands::policies::POLICIES_FILE;
+use commands::transfers::TRANSFERS_FILE;
+use commands::webhooks::{self, DELIVERY_LOG, WEBHOOKS_FILE};
+use commands::{
+    bare_repos, clean_empty_parent_folders, enforce_git_suffix, filter_repos, get_repo_home,
+    is_bare_repo, is_server, represents_repo,
+};
+use std::fs;
+use std::os::unix;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Files and folders the server keeps in the repository folder, left alone when it is checked
+const SERVER_FILES: [&str; 8] = [
+    POLICIES_FILE,
+    WATCHERS_FILE,
+    email::QUEUE_FOLDER,
+    WEBHOOKS_FILE,
+    DELIVERY_LOG,
+    webhooks::QUEUE_FOLDER,
+    CHANGES_FILE,
+    TRANSFERS_FILE,
+];
+
+/// Find and repair broken repository links
+#[derive(Parser)]
+#[command(about)]
+struct Cli {
+    /// Repair problems where possible
+    #[arg(long)]
+    fix: bool,
+}
+
+enum Problem {
+    /// Link whose repository no longer exists
+    DanglingLink(PathBuf),
+    /// Link pointing somewhere other than its repository
+    WrongTarget(PathBuf),
+    /// Link to a repository without a `.git` suffix
+    MissingSuffix(PathBuf),
+    /// Repository without a link
+    Orphan(PathBuf),
+    /// Repository left behind in the temp folder by an interrupted move
+    Staged(PathBuf),
+    /// Temp folder left behind by an interrupted move
+    TmpFolder(PathBuf),
+    /// Empty folder in the home or repository folder
+    EmptyFolder(PathBuf),
+    /// Members of an organisation not written by the server, which are ignored
+    UntrustedMembers(PathBuf),
+}
+
+impl Problem {
+    fn describe(&self) -> String {
+        match self {
+            Problem::DanglingLink(path) => format!("Dangling link '{}'", path.display()),
+            Problem::WrongTarget(path) => format!("Misdirected link '{}'", path.display()),
+            Problem::MissingSuffix(path) => format!("Missing suffix '{}'", path.display()),
+            Problem::Orphan(path) => format!("Orphaned repository '{}'", path.display()),
+            Problem::Staged(path) => format!("Interrupted move '{}'", path.display()),
+            Problem::TmpFolder(path) => format!("Leftover temp folder '{}'", path.display()),
+            Problem::EmptyFolder(path) => format!("Empty folder '{}'", path.display()),
+            Problem::UntrustedMembers(path) => {
+                format!("Members not owned by root '{}'", path.display())
+            }
+        }
+    }
+
+    fn fix(&self, repo_home: &Path) -> Result<String, String> {
+        match self {
+            Problem::DanglingLink(path) => {
+                fs::remove_file(path).map_err(|e| e.to_string())?;
+                clean_empty_parent_folders(path, None);
+                Ok(String::from("Removed link"))
+            }
+            Problem::WrongTarget(path) => {
+                let git_dir = repo_home.join(path);
+                if !is_bare_repo(&git_dir) {
+                    return Err(String::from("No repository exists for this link"));
+                }
+                fs::remove_file(path).map_err(|e| e.to_string())?;
+                unix::fs::symlink(git_dir, path).map_err(|e| e.to_string())?;
+                Ok(String::from("Relinked repository"))
+            }
+            Problem::MissingSuffix(path) => {
+                let dst = enforce_git_suffix(path.to_path_buf())?;
+                let git_src = repo_home.join(path);
+                let git_dst = repo_home.join(&dst);
+                if dst.exists() || dst.is_symlink() || git_dst.exists() {
+                    return Err(format!("'{}' is occupied", dst.display()));
+                }
+                fs::rename(&git_src, &git_dst).map_err(|e| e.to_string())?;
+                unix::fs::symlink(git_dst, &dst).map_err(|e| e.to_string())?;
+                fs::remove_file(path).map_err(|e| e.to_string())?;
+                Ok(format!("Renamed to '{}'", dst.display()))
+            }
+            Problem::Orphan(path) => {
+                link_repo(repo_home, path)?;
+                Ok(String::from("Relinked repository"))
+            }
+            Problem::Staged(path) => {
+                let git_src = repo_home.join(&config().tmp_dir).join(path);
+                let git_dst = repo_home.join(path);
+                if git_dst.exists() {
+                    return Err(format!("'{}' is occupied", path.display()));
+                }
+                if let Some(parent) = git_dst.parent() {
+                    fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+                }
+                fs::rename(&git_src, &git_dst).map_err(|e| e.to_string())?;
+                if !path.is_symlink() {
+                    link_repo(repo_home, path)?;
+                }
+                Ok(String::from("Restored repository"))
+            }
+            Problem::TmpFolder(path) => {
+                if !bare_repos(path).is_empty() {
+                    return Err(String::from("Folder still contains repositories"));
+                }
+                fs::remove_dir_all(path).map_err(|e| e.to_string())?;
+                Ok(String::from("Removed folder"))
+            }
+            Problem::EmptyFolder(path) => {
+                if fs::read_dir(path)
+                    .map_err(|e| e.to_string())?
+                    .next()
+                    .is_some()
+                {
+                    return Ok(String::from("Folder is no longer empty"));
+                }
+                fs::remove_dir(path).map_err(|e| e.to_string())?;
+                let stop = path.starts_with(repo_home).then_some(repo_home);
+                clean_empty_parent_folders(path, stop);
+                Ok(String::from("Removed folder"))
+            }
+            Problem::UntrustedMembers(path) => {
+                if !is_server() {
+                    return Err(String::from("only the server can take over members"));
+                }
+                if path.is_symlink() {
+                    return Err(String::from(
+                        "members are a link, restore them from a backup",
+                    ));
+                }
+                unix::fs::lchown(path, Some(0), Some(0)).map_err(|e| e.to_string())?;
+                fs::set_permissions(path, fs::Permissions::from_mode(0o644))
+                    .map_err(|e| e.to_string())?;
+                Ok(String::from("Gave members to root"))
+            }
+        }
+    }
+}
+
+fn main() {
+    let args = Cli::parse();
+
+    let repo_home = get_repo_home();
+    let tmp_home = repo_home.join(&config().tmp_dir);
+    let mut problems = Vec::new();
+
+    for path in filter_repos(None, false, |_| true) {
+        let git_dir = repo_home.join(&path);
+        let misdirected = path.read_link().is_ok_and(|target| !target.eq(&git_dir));
+        if misdirected && (path.exists() || is_bare_repo(&git_dir)) {
+            problems.push(Problem::WrongTarget(path));
+        } else if !path.exists() {
+            problems.push(Problem::DanglingLink(path));
+        } else if !represents_repo(&path) {
+            problems.push(Problem::MissingSuffix(path));
+        }
+    }
+
+    for path in bare_repos(&repo_home) {
+        if path.starts_with(&config().tmp_dir) {
+            continue;
+        }
+        if !path.is_symlink() {
+            problems.push(Problem::Orphan(path));
+        }
+    }
+
+    if tmp_home.is_dir() {
+        for path in bare_repos(&tmp_home) {
+            problems.push(Problem::Staged(path));
+        }
+        problems.push(Problem::TmpFolder(tmp_home.clone()));
+    }
+
+    for org in all_orgs() {
+        let path = org_dir(&org).join(MEMBERS_FILE);
+        if fs::symlink_metadata(&path).is_ok_and(|m| m.uid() != 0 || !m.is_file()) {
+            problems.push(Problem::UntrustedMembers(path));
+        }
+    }
+
+    for path in empty_folders(Path::new("."), &[".ssh", "git-shell-commands"]) {
+        problems.push(Problem::EmptyFolder(path));
+    }
+    let mut skipped = vec![config().tmp_dir.as_str()];
+    skipped.extend(SERVER_FILES);
+    for path in empty_folders(&repo_home, &skipped) {
+        problems.push(Problem::EmptyFolder(path));
+    }
+
+    if problems.is_empty() {
+        println!("No problems found");
+        return;
+    }
+
+    if !args.fix {
+        for problem in &problems {
+            println!("{}", problem.describe());
+        }
+        match problems.len() {
+            1 => println!("Found 1 problem, run 'doctor --fix' to repair it"),
+            n => println!("Found {} problems, run 'doctor --fix' to repair them", n),
+        }
+        std::process::exit(1);
+    }
+
+    let mut fixed = 0;
+    for problem in &problems {
+        match problem.fix(&repo_home) {
+            Ok(action) => {
+                println!("{} : {}", problem.describe(), action);
+                fixed += 1;
+            }
+            Err(err) => eprintln!("{} : Cannot fix, {}", problem.describe(), err),
+        }
+    }
+
+    println!("Fixed {}/{} problems", fixed, problems.len());
+    if fixed < problems.len() {
+        std::process::exit(1);
+    }
+}
+
+fn link_repo(repo_home: &Path, path: &Path) -> Result<(), String> {
+    if path.exists() || path.is_symlink() {
+        return Err(format!("'{}' is occupied", path.display()));
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    unix::fs::symlink(repo_home.join(path), path).map_err(|e| e.to_string())
+}
+
+fn empty_folders(root: &Path, skip: &[&str]) -> Vec<PathBuf> {
+    WalkDir::new(root)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|entry| {
+            let skipped = entry.depth() == 1 && skip.iter().any(|s| entry.file_name().eq(*s));
+            !skipped && !is_bare_repo(entry.path())
+        })
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_dir())
+        .filter(|entry| {
+            fs::read_dir(entry.path()).is_ok_and(|mut contents| contents.next().is_none())
+        })
+        .map(|entry| match root.eq(Path::new(".")) {
+            true => entry.path().strip_prefix(".").unwrap().to_path_buf(),
+            false => entry.into_path(),
+        })
+        .collect()
+}
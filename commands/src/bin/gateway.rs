@@ -0,0 +1,208 @@
+use clap::Parser;
+use commands::config::config;
+use commands::orgs::{org_dir, org_folder_name, repo_org, role};
+use commands::{
+    is_bare_repo, parse_repo_path, parse_username, BASE_USER_PATH, GATEWAY_ACCOUNT,
+    GATEWAY_USER_VAR,
+};
+use std::fs;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::{env, io};
+
+const GATEWAY_PATH: &str = "/srv/bin/gateway";
+const KEY_OPTIONS: &str = "no-port-forwarding,no-X11-forwarding,no-agent-forwarding";
+/// Prefixes of key types, telling keys apart from a leading list of options
+const KEY_TYPE_PREFIXES: [&str; 3] = ["ssh-", "ecdsa-", "sk-"];
+/// Options of a user's keys kept in the gateway's entries, as they only restrict the key
+const KEPT_KEY_OPTIONS: [&str; 2] = ["from=", "expiry-time="];
+/// Commands git-shell runs on a repository named by their argument
+const GIT_COMMANDS: [&str; 3] = ["git-receive-pack", "git-upload-pack", "git-upload-archive"];
+
+/// Forced command wrapper serving all users through a single account
+#[derive(Parser)]
+#[command(about, arg_required_else_help = true)]
+struct Cli {
+    /// User the connecting key belongs to
+    #[arg(value_parser = clap::builder::ValueParser::new(parse_username))]
+    user: Option<String>,
+    /// Print authorized_keys entries for every user if ACCOUNT is the gateway account
+    #[arg(long, value_name = "ACCOUNT", conflicts_with = "user")]
+    authorized_keys: Option<String>,
+}
+
+fn main() {
+    let args = Cli::parse();
+
+    if let Some(account) = args.authorized_keys {
+        if account == GATEWAY_ACCOUNT {
+            print_authorized_keys();
+        }
+        return;
+    }
+
+    let user = args.user.unwrap();
+    let home = Path::new(BASE_USER_PATH).join(&user);
+    if !home.is_dir() {
+        eprintln!("Unknown user '{}'", user);
+        process::exit(1);
+    }
+    env::set_current_dir(&home).expect("Failed to enter user home");
+
+    let mut shell = Command::new("git-shell");
+    shell.env("HOME", &home).env(GATEWAY_USER_VAR, &user);
+    if let Ok(command) = env::var("SSH_ORIGINAL_COMMAND") {
+        match check_command(&user, &home, &command) {
+            Ok(command) => shell.arg("-c").arg(command),
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        };
+    }
+
+    let err = shell.exec();
+    eprintln!("Failed to start git-shell: {}", err);
+    process::exit(1);
+}
+
+/// Ensure git commands only reach repositories of the user, rewriting their path
+///
+/// As the gateway account owns every repository, git-shell would otherwise serve any path.
+/// Other commands are run from the user's `git-shell-commands`, which check their own paths.
+fn check_command(user: &str, home: &Path, command: &str) -> Result<String, String> {
+    let command = match command.strip_prefix("git ") {
+        Some(rest) => format!("git-{}", rest),
+        None => command.to_string(),
+    };
+    let (program, arg) = command.split_once(' ').unwrap_or((&command, ""));
+    if !GIT_COMMANDS.contains(&program) {
+        return Ok(command);
+    }
+
+    let arg = arg.trim();
+    let path = arg
+        .strip_prefix('\'')
+        .and_then(|arg| arg.strip_suffix('\''))
+        .unwrap_or(arg);
+    if path.contains(['\'', ' ']) {
+        return Err(String::from("Invalid repository path"));
+    }
+    let path = resolve_repo(user, home, path).map_err(|err| format!("'{}' : {}", path, err))?;
+    Ok(format!("{} '{}'", program, path.display()))
+}
+
+/// Path of a repository relative to the user's home, if it is theirs or their organisation's
+fn resolve_repo(user: &str, home: &Path, path: &str) -> Result<PathBuf, String> {
+    let path = path
+        .strip_prefix("~/")
+        .unwrap_or(path)
+        .trim_end_matches('/');
+    if path.starts_with('~') {
+        return Err(String::from("Paths of other users are not allowed"));
+    }
+    let path = parse_repo_path(path)?;
+
+    let root = match repo_org(&path) {
+        Some(org) if role(&org, user).is_none() => {
+            return Err(format!("Not a member of '{}'", org_folder_name(&org)))
+        }
+        Some(org) => org_dir(&org),
+        None => config().repo_path.join(user),
+    };
+    let not_found = || String::from("No repository found");
+    let git_dir = home.join(&path).canonicalize().map_err(|_| not_found())?;
+    let root = root.canonicalize().map_err(|_| not_found())?;
+    if !git_dir.starts_with(&root) || !is_bare_repo(&git_dir) {
+        return Err(not_found());
+    }
+    Ok(path)
+}
+
+fn print_authorized_keys() {
+    let users = match fs::read_dir(BASE_USER_PATH) {
+        Ok(users) => users,
+        Err(err) => {
+            eprintln!("Failed to read users: {}", err);
+            process::exit(1);
+        }
+    };
+
+    for entry in users.filter_map(Result::ok) {
+        if !entry.file_type().is_ok_and(|t| t.is_dir()) {
+            continue;
+        }
+        let name = entry.file_name();
+        let Some(user) = name.to_str().and_then(|n| parse_username(n).ok()) else {
+            continue;
+        };
+
+        let keys = match fs::read_to_string(entry.path().join(".ssh/authorized_keys")) {
+            Ok(keys) => keys,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => {
+                eprintln!("Failed to read keys for '{}': {}", user, err);
+                continue;
+            }
+        };
+
+        for line in keys.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (options, key) = split_key_options(line);
+            if key.is_empty() {
+                continue;
+            }
+            let mut entry_options =
+                format!("command=\"{} {}\",{}", GATEWAY_PATH, user, KEY_OPTIONS);
+            for option in options {
+                let lowercase = option.to_ascii_lowercase();
+                if KEPT_KEY_OPTIONS
+                    .iter()
+                    .any(|kept| lowercase.starts_with(kept))
+                {
+                    entry_options.push(',');
+                    entry_options.push_str(option);
+                }
+            }
+            println!("{} {}", entry_options, key);
+        }
+    }
+}
+
+/// Split the options a line of authorized_keys may start with from its key
+///
+/// Options are separated by commas and may quote values holding commas, spaces and
+/// escaped quotes, such as `from="10.0.0.0/8,*.example.com"`.
+fn split_key_options(line: &str) -> (Vec<&str>, &str) {
+    if KEY_TYPE_PREFIXES
+        .iter()
+        .any(|prefix| line.starts_with(prefix))
+    {
+        return (Vec::new(), line);
+    }
+
+    let mut options = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                options.push(&line[start..i]);
+                start = i + 1;
+            }
+            c if c.is_whitespace() && !quoted => {
+                options.push(&line[start..i]);
+                return (options, line[i..].trim_start());
+            }
+            _ => (),
+        }
+    }
+    (options, "")
+}
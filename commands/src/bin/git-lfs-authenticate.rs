@@ -0,0 +1,55 @@
+use clap::{Parser, ValueEnum};
+use commands::orgs::{require_role, Role};
+use commands::{is_archived, parse_repo_path, repo_git_dir};
+use std::path::PathBuf;
+use std::process;
+
+/// Point older git-lfs clients at the SSH transfer protocol, as there is no LFS HTTP API
+#[derive(Parser)]
+#[command(about, arg_required_else_help = true)]
+struct Cli {
+    /// Path to repository, as given in the remote URL
+    #[arg(value_parser = clap::builder::ValueParser::new(parse_remote_path))]
+    path: PathBuf,
+    /// Whether the client is uploading or downloading objects
+    operation: Operation,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Operation {
+    Upload,
+    Download,
+}
+
+fn parse_remote_path(path: &str) -> Result<PathBuf, String> {
+    let path = path.strip_prefix("~/").unwrap_or(path);
+    parse_repo_path(path.trim_start_matches('/'))
+}
+
+fn main() {
+    let args = Cli::parse();
+
+    if let Err(err) = check_access(&args) {
+        eprintln!("'{}' : {}", args.path.display(), err);
+        process::exit(1);
+    }
+    eprintln!(
+        "'{}' : Git LFS objects are only served over SSH by git-lfs-transfer, which needs git-lfs 3.0 or newer",
+        args.path.display()
+    );
+    process::exit(1);
+}
+
+/// Apply the checks of git-lfs-transfer, so clients learn about missing access first
+fn check_access(args: &Cli) -> Result<(), String> {
+    let git_dir = repo_git_dir(&args.path)?;
+    if args.operation == Operation::Download {
+        return require_role(&args.path, Role::Read);
+    }
+    if is_archived(&git_dir) {
+        return Err(String::from(
+            "Repository is archived and read-only, unarchive it to upload",
+        ));
+    }
+    require_role(&args.path, Role::Write)
+}
@@ -0,0 +1,382 @@
+use clap::{Parser, ValueEnum};
+use commands::config::size_limits;
+use commands::lfs::{object_path, object_size, parse_oid, store_object, usage};
+use commands::orgs::{require_role, Role};
+use commands::stats::format_size;
+use commands::{is_archived, parse_repo_path, relative_repo_path, repo_git_dir};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Largest payload of a single pkt-line
+const MAX_PACKET_DATA: usize = 65516;
+
+/// Serve Git LFS objects over SSH, run by git-lfs itself
+#[derive(Parser)]
+#[command(about, arg_required_else_help = true)]
+struct Cli {
+    /// Path to repository, as given in the remote URL
+    #[arg(value_parser = clap::builder::ValueParser::new(parse_remote_path))]
+    path: PathBuf,
+    /// Whether the client is uploading or downloading objects
+    operation: Operation,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Operation {
+    Upload,
+    Download,
+}
+
+fn parse_remote_path(path: &str) -> Result<PathBuf, String> {
+    let path = path.strip_prefix("~/").unwrap_or(path);
+    parse_repo_path(path.trim_start_matches('/'))
+}
+
+enum Packet {
+    Data(Vec<u8>),
+    Flush,
+    Delim,
+}
+
+/// A failed request, reported to the client with an HTTP-like status code
+struct Failure(u16, String);
+
+struct Connection<R, W> {
+    input: R,
+    output: W,
+}
+
+impl<R: Read, W: Write> Connection<R, W> {
+    fn read_packet(&mut self) -> io::Result<Packet> {
+        let mut length = [0; 4];
+        self.input.read_exact(&mut length)?;
+        let length = std::str::from_utf8(&length)
+            .ok()
+            .and_then(|length| usize::from_str_radix(length, 16).ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid packet length"))?;
+        match length {
+            0 => Ok(Packet::Flush),
+            1 => Ok(Packet::Delim),
+            2..=4 => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid packet length",
+            )),
+            _ => {
+                let mut data = vec![0; length - 4];
+                self.input.read_exact(&mut data)?;
+                Ok(Packet::Data(data))
+            }
+        }
+    }
+
+    /// Read text lines up to the next flush or delimiter, returning which ended them
+    fn read_lines(&mut self) -> io::Result<(Vec<String>, bool)> {
+        let mut lines = Vec::new();
+        loop {
+            match self.read_packet()? {
+                Packet::Data(data) => {
+                    let line = String::from_utf8_lossy(&data);
+                    lines.push(line.trim_end_matches('\n').to_string());
+                }
+                Packet::Flush => return Ok((lines, false)),
+                Packet::Delim => return Ok((lines, true)),
+            }
+        }
+    }
+
+    fn write_data(&mut self, data: &[u8]) -> io::Result<()> {
+        write!(self.output, "{:04x}", data.len() + 4)?;
+        self.output.write_all(data)
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        self.write_data(format!("{}\n", line).as_bytes())
+    }
+
+    fn write_flush(&mut self) -> io::Result<()> {
+        self.output.write_all(b"0000")?;
+        self.output.flush()
+    }
+
+    fn write_delim(&mut self) -> io::Result<()> {
+        self.output.write_all(b"0001")
+    }
+
+    fn write_status(&mut self, args: &[String], lines: &[String]) -> io::Result<()> {
+        self.write_line("status 200")?;
+        for arg in args {
+            self.write_line(arg)?;
+        }
+        if !lines.is_empty() {
+            self.write_delim()?;
+            for line in lines {
+                self.write_line(line)?;
+            }
+        }
+        self.write_flush()
+    }
+
+    fn write_failure(&mut self, Failure(code, message): Failure) -> io::Result<()> {
+        self.write_line(&format!("status {}", code))?;
+        self.write_delim()?;
+        self.write_line(&message)?;
+        self.write_flush()
+    }
+}
+
+/// Reads the data packets of a request until its flush
+struct PacketReader<'a, R, W> {
+    connection: &'a mut Connection<R, W>,
+    buffer: Vec<u8>,
+    position: usize,
+    done: bool,
+}
+
+impl<R: Read, W: Write> Read for PacketReader<'_, R, W> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.buffer.len() {
+            if self.done {
+                return Ok(0);
+            }
+            match self.connection.read_packet()? {
+                Packet::Data(data) => {
+                    self.buffer = data;
+                    self.position = 0;
+                }
+                Packet::Flush => self.done = true,
+                Packet::Delim => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Unexpected delimiter",
+                    ))
+                }
+            }
+        }
+        let count = out.len().min(self.buffer.len() - self.position);
+        out[..count].copy_from_slice(&self.buffer[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+impl<R: Read, W: Write> PacketReader<'_, R, W> {
+    /// Skip the rest of the data so the next request can be read
+    fn drain(&mut self) -> io::Result<()> {
+        io::copy(self, &mut io::sink()).map(|_| ())
+    }
+}
+
+fn main() {
+    let args = Cli::parse();
+
+    let git_dir = repo_git_dir(&args.path).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut connection = Connection {
+        input: BufReader::new(stdin.lock()),
+        output: BufWriter::new(stdout.lock()),
+    };
+    if let Err(err) = serve(&mut connection, &git_dir, args.operation) {
+        eprintln!("'{}' : {}", args.path.display(), err);
+        process::exit(1);
+    }
+}
+
+fn serve<R: Read, W: Write>(
+    connection: &mut Connection<R, W>,
+    git_dir: &Path,
+    operation: Operation,
+) -> io::Result<()> {
+    connection.write_line("version=1")?;
+    connection.write_flush()?;
+    let (lines, _) = connection.read_lines()?;
+    if lines.first().map(String::as_str) != Some("version 1") {
+        return connection.write_failure(Failure(400, String::from("Unsupported version")));
+    }
+    connection.write_status(&[], &[])?;
+
+    loop {
+        let (lines, delimited) = match connection.read_lines() {
+            Ok(request) => request,
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        let Some((command, args)) = lines.split_first() else {
+            continue;
+        };
+        let (name, argument) = command.split_once(' ').unwrap_or((command, ""));
+
+        let result = match name {
+            "batch" => {
+                let objects = match delimited {
+                    true => connection.read_lines()?.0,
+                    false => Vec::new(),
+                };
+                batch(git_dir, operation, args, &objects)
+                    .map(|lines| connection.write_status(&[], &lines))
+            }
+            "put-object" => {
+                let mut data = PacketReader {
+                    connection,
+                    buffer: Vec::new(),
+                    position: 0,
+                    done: !delimited,
+                };
+                let result = put_object(git_dir, operation, argument, args, &mut data);
+                data.drain()?;
+                result.map(|()| connection.write_status(&[], &[]))
+            }
+            "verify-object" => {
+                verify_object(git_dir, argument, args).map(|()| connection.write_status(&[], &[]))
+            }
+            "get-object" => get_object(connection, git_dir, argument),
+            "quit" => return connection.write_status(&[], &[]),
+            "lock" | "list-lock" | "unlock" => Err(Failure(
+                501,
+                String::from("Locking is not supported by this server"),
+            )),
+            _ => Err(Failure(400, format!("Unknown command '{}'", name))),
+        };
+        match result {
+            Ok(written) => written?,
+            Err(failure) => connection.write_failure(failure)?,
+        }
+    }
+}
+
+fn argument<'a>(args: &'a [String], key: &str) -> Option<&'a str> {
+    args.iter()
+        .find_map(|arg| arg.strip_prefix(key)?.strip_prefix('='))
+}
+
+fn check_writable(git_dir: &Path, operation: Operation) -> Result<(), Failure> {
+    if operation != Operation::Upload {
+        return Err(Failure(
+            403,
+            String::from("Objects can only be uploaded by upload connections"),
+        ));
+    }
+    if is_archived(git_dir) {
+        return Err(Failure(
+            403,
+            String::from("Repository is archived and read-only, unarchive it to upload"),
+        ));
+    }
+    let path = relative_repo_path(git_dir)
+        .ok_or_else(|| Failure(403, String::from("Not one of your repositories")))?;
+    require_role(&path, Role::Write).map_err(|e| Failure(403, e))
+}
+
+fn batch(
+    git_dir: &Path,
+    operation: Operation,
+    args: &[String],
+    objects: &[String],
+) -> Result<Vec<String>, Failure> {
+    if argument(args, "hash-algo").is_some_and(|algo| algo != "sha256") {
+        return Err(Failure(
+            400,
+            String::from("Only sha256 object IDs are supported"),
+        ));
+    }
+    if operation == Operation::Upload {
+        check_writable(git_dir, operation)?;
+    }
+
+    let mut lines = Vec::new();
+    for object in objects {
+        let (oid, size) = object.split_once(' ').unwrap_or((object, ""));
+        let oid = parse_oid(oid).map_err(|e| Failure(400, e))?;
+        let size = size
+            .split(' ')
+            .next()
+            .and_then(|size| size.parse::<u64>().ok())
+            .ok_or_else(|| Failure(400, format!("Invalid size for '{}'", oid)))?;
+        let present = object_size(git_dir, &oid) == Some(size);
+        let action = match (operation, present) {
+            (Operation::Upload, true) => "noop",
+            (Operation::Upload, false) => "upload",
+            (Operation::Download, true) => "download",
+            (Operation::Download, false) => "noop",
+        };
+        lines.push(format!("{} {} {}", oid, size, action));
+    }
+    Ok(lines)
+}
+
+fn put_object(
+    git_dir: &Path,
+    operation: Operation,
+    oid: &str,
+    args: &[String],
+    data: &mut impl Read,
+) -> Result<(), Failure> {
+    check_writable(git_dir, operation)?;
+    let oid = parse_oid(oid).map_err(|e| Failure(400, e))?;
+    let size = argument(args, "size")
+        .and_then(|size| size.parse::<u64>().ok())
+        .ok_or_else(|| Failure(400, String::from("Missing object size")))?;
+    if object_size(git_dir, &oid) == Some(size) {
+        return Ok(());
+    }
+
+    let path = relative_repo_path(git_dir).unwrap_or_default();
+    if let Some(max_lfs) = size_limits(&path).max_lfs {
+        let (_, used) = usage(git_dir);
+        if used + size > max_lfs {
+            return Err(Failure(
+                507,
+                format!(
+                    "Storing {} would exceed the LFS limit of {}, {} is already used",
+                    format_size(size),
+                    format_size(max_lfs),
+                    format_size(used)
+                ),
+            ));
+        }
+    }
+    store_object(git_dir, &oid, size, data).map_err(|e| Failure(400, e))
+}
+
+fn verify_object(git_dir: &Path, oid: &str, args: &[String]) -> Result<(), Failure> {
+    let oid = parse_oid(oid).map_err(|e| Failure(400, e))?;
+    let size = argument(args, "size").and_then(|size| size.parse::<u64>().ok());
+    match object_size(git_dir, &oid) {
+        Some(stored) if Some(stored) == size => Ok(()),
+        Some(_) => Err(Failure(409, format!("Size of '{}' does not match", oid))),
+        None => Err(Failure(404, format!("No object '{}'", oid))),
+    }
+}
+
+fn get_object<R: Read, W: Write>(
+    connection: &mut Connection<R, W>,
+    git_dir: &Path,
+    oid: &str,
+) -> Result<io::Result<()>, Failure> {
+    let oid = parse_oid(oid).map_err(|e| Failure(400, e))?;
+    let size =
+        object_size(git_dir, &oid).ok_or_else(|| Failure(404, format!("No object '{}'", oid)))?;
+    let mut file = File::open(object_path(git_dir, &oid))
+        .map_err(|e| Failure(500, format!("Failed to read '{}': {}", oid, e)))?;
+
+    Ok((|| {
+        connection.write_line("status 200")?;
+        connection.write_line(&format!("size={}", size))?;
+        connection.write_delim()?;
+        let mut buffer = vec![0; MAX_PACKET_DATA];
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            connection.write_data(&buffer[..read])?;
+        }
+        connection.write_flush()
+    })())
+}
@@ -20,13 +20,39 @@ fn main() {
         println!(
             " \
 Available commands:
-  help    : list all commands or get help for a specific command
-  aliases : list all aliases for a command
+  help     : list all commands or get help for a specific command
+  aliases  : list all aliases for a command
+  config   : show the server configuration in effect
 
-  create  : create new repositories
-  delete  : delete existing repositories
-  move    : rename a single or move multiple repositories
-  list    : list all repositories matching any filters"
+  create   : create new repositories
+  delete   : delete existing repositories
+  move     : rename a single or move multiple repositories
+  list     : list all repositories matching any filters
+  transfer : give repositories to another user, or accept theirs
+  org      : manage organisations sharing repositories between members
+  stats    : show disk usage of repositories
+  archive  : make repositories read-only
+  unarchive: make archived repositories writable again
+  snapshot : download an archive of a repository at any revision
+  log      : show the commit history of a repository
+  show     : show a commit, or a file or folder at a revision
+  branches : list the branches of a repository
+  branch   : delete or rename a branch of a repository
+  tags     : list the tags of a repository
+  mr       : review and merge branches of a repository
+  issue    : track issues of a repository
+  webhooks : notify other services of pushes and repository changes
+  watch    : get emails about pushes to repositories
+  ci       : show the results of jobs run for pushed commits
+  status   : report and list the statuses of commits
+  checks   : list or change the optional checks run on pushes
+  policy   : enforce rules for commit messages, authors and paths
+  doctor   : find and repair broken repository links
+  maintain : run housekeeping on repositories
+  verify   : check the integrity of repositories
+
+Commands selecting repositories accept globs, '!' to exclude a glob, and the
+filters 'size>10M', 'updated<30d', 'is:empty' and 'is:archived'"
         );
         return;
     }
@@ -0,0 +1,35 @@
+use commands::hooks::{post_receive, pre_receive, read_ref_updates};
+use std::path::{Path, PathBuf};
+use std::{env, process};
+
+/// Server hook installed for every repository, dispatching on the name it is run as
+fn main() {
+    let name = env::args()
+        .next()
+        .as_deref()
+        .map(Path::new)
+        .and_then(Path::file_name)
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let git_dir = match env::var("GIT_DIR") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => PathBuf::from("."),
+    };
+
+    match name.as_str() {
+        "pre-receive" => {
+            let updates = read_ref_updates();
+            if let Err(problems) = pre_receive(&git_dir, &updates) {
+                for problem in problems {
+                    eprintln!("{}", problem);
+                }
+                process::exit(1);
+            }
+        }
+        "post-receive" => post_receive(&git_dir, &read_ref_updates()),
+        _ => {
+            eprintln!("Unknown hook '{}'", name);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use clap::{Args, Parser, Subcommand};
+use commands::events::{append_event, event_ids, event_ref, parse_comment, parse_title};
+use commands::issues::{issues, Issue, ISSUE_PREFIX};
+use commands::stats::format_age;
+use commands::{is_archived, parse_repo_path, repo_git_dir};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Track issues of a repository
+#[derive(Parser)]
+#[command(about, arg_required_else_help = true)]
+struct Cli {
+    #[command(subcommand)]
+    action: Action,
+}
+
+#[derive(Subcommand)]
+enum Action {
+    /// Open a new issue
+    New {
+        /// Path to repository
+        #[arg(value_parser = clap::builder::ValueParser::new(parse_repo_path))]
+        path: PathBuf,
+        /// Title of the issue
+        #[arg(value_parser = clap::builder::ValueParser::new(parse_title))]
+        title: String,
+        /// Description of the issue
+        #[arg(short, long, value_parser = clap::builder::ValueParser::new(parse_comment))]
+        message: Option<String>,
+    },
+    /// List issues
+    List {
+        /// Path to repository
+        #[arg(value_parser = clap::builder::ValueParser::new(parse_repo_path))]
+        path: PathBuf,
+        /// Include closed issues
+        #[arg(short, long)]
+        all: bool,
+    },
+    /// Show an issue and its comments
+    Show(Target),
+    /// Comment on an issue
+    Comment {
+        #[command(flatten)]
+        target: Target,
+        /// Text of the comment
+        #[arg(value_parser = clap::builder::ValueParser::new(parse_comment))]
+        message: String,
+    },
+    /// Close an issue
+    Close(Target),
+    /// Reopen a closed issue
+    Reopen(Target),
+}
+
+#[derive(Args)]
+struct Target {
+    /// Path to repository
+    #[arg(value_parser = clap::builder::ValueParser::new(parse_repo_path))]
+    path: PathBuf,
+    /// Number of the issue
+    id: u64,
+}
+
+fn main() {
+    let args = Cli::parse();
+
+    let path = match &args.action {
+        Action::New { path, .. } | Action::List { path, .. } => path,
+        Action::Show(target) | Action::Close(target) | Action::Reopen(target) => &target.path,
+        Action::Comment { target, .. } => &target.path,
+    };
+    let git_dir = repo_git_dir(path).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+
+    let read_only = matches!(args.action, Action::List { .. } | Action::Show(_));
+    if !read_only && is_archived(&git_dir) {
+        eprintln!(
+            "'{}' : Repository is archived and read-only, unarchive it to change issues",
+            path.display()
+        );
+        process::exit(1);
+    }
+
+    let result = match &args.action {
+        Action::New { title, message, .. } => new(&git_dir, title, message.as_deref()),
+        Action::List { all, .. } => {
+            list(&git_dir, *all);
+            Ok(())
+        }
+        Action::Show(target) => show(&git_dir, target.id),
+        Action::Comment { target, message } => comment(&git_dir, target.id, message),
+        Action::Close(target) => set_open(&git_dir, target.id, false),
+        Action::Reopen(target) => set_open(&git_dir, target.id, true),
+    };
+    if let Err(err) = result {
+        eprintln!("'{}' : {}", path.display(), err);
+        process::exit(1);
+    }
+}
+
+fn new(git_dir: &Path, title: &str, message: Option<&str>) -> Result<(), String> {
+    let id = event_ids(git_dir, ISSUE_PREFIX).last().unwrap_or(&0) + 1;
+    append_event(
+        git_dir,
+        &event_ref(ISSUE_PREFIX, id),
+        &[("action", "open"), ("title", title)],
+        message.unwrap_or_default(),
+    )?;
+    println!("Opened issue #{}", id);
+    Ok(())
+}
+
+fn list(git_dir: &Path, all: bool) {
+    let issues = issues(git_dir)
+        .into_iter()
+        .filter(|issue| all || issue.open)
+        .collect::<Vec<_>>();
+    if issues.is_empty() {
+        println!("No issues found");
+        return;
+    }
+
+    println!("{:<5} {:<7} {:<8} TITLE", "ID", "STATE", "COMMENTS");
+    for issue in &issues {
+        println!(
+            "{:<5} {:<7} {:<8} {}",
+            format!("#{}", issue.id),
+            if issue.open { "open" } else { "closed" },
+            issue.comments(),
+            issue.title
+        );
+    }
+}
+
+fn show(git_dir: &Path, id: u64) -> Result<(), String> {
+    let issue = Issue::load(git_dir, id)?;
+    println!("#{} {}", issue.id, issue.title);
+
+    for event in &issue.events {
+        let when = format_age(event.time);
+        let heading = match event.action() {
+            "open" => format!("Opened by {} {}", event.author, when),
+            "comment" => format!("{} commented {}:", event.author, when),
+            "close" => format!("Closed by {} {}", event.author, when),
+            "reopen" => format!("Reopened by {} {}", event.author, when),
+            _ => continue,
+        };
+        println!("\n{}", heading);
+        for line in event.text.lines() {
+            println!("    {}", line);
+        }
+    }
+    Ok(())
+}
+
+fn comment(git_dir: &Path, id: u64, message: &str) -> Result<(), String> {
+    Issue::load(git_dir, id)?;
+    append_event(
+        git_dir,
+        &event_ref(ISSUE_PREFIX, id),
+        &[("action", "comment")],
+        message,
+    )?;
+    println!("Commented on issue #{}", id);
+    Ok(())
+}
+
+fn set_open(git_dir: &Path, id: u64, open: bool) -> Result<(), String> {
+    let issue = Issue::load(git_dir, id)?;
+    if issue.open == open {
+        let state = if open { "open" } else { "closed" };
+        return Err(format!("Issue #{} is already {}", id, state));
+    }
+    let action = if open { "reopen" } else { "close" };
+    append_event(
+        git_dir,
+        &event_ref(ISSUE_PREFIX, id),
+        &[("action", action)],
+        "",
+    )?;
+    match open {
+        true => println!("Reopened issue #{}", id),
+        false => println!("Closed issue #{}", id),
+    }
+    Ok(())
+}
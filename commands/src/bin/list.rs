@@ -1,41 +1,80 @@
 use clap::Parser;
-use commands::{filter_repos, make_glob_set, parse_repo_glob};
-use globset::Glob;
-use std::iter;
+use commands::filter::{parse_repo_filter, FilterTerm, RepoFilter};
+use commands::issues::open_issues;
+use commands::orgs::{repo_org, role, Role};
+use commands::{filter_repos, get_repo_home, get_username, is_archived};
+use std::collections::HashMap;
 
 /// List all repositories matching any filters
 #[derive(Parser)]
 #[command(about)]
 struct Cli {
-    /// Filters to apply to results
-    #[arg(num_args = 0.., value_parser = clap::builder::ValueParser::new(parse_repo_glob))]
-    filter: Option<Vec<Glob>>,
+    /// Filters to apply to results, such as `dir/*`, `!dir/old-*`, `size>10M`, `updated<30d`,
+    /// `is:empty` or `is:archived`
+    #[arg(num_args = 0.., value_parser = clap::builder::ValueParser::new(parse_repo_filter))]
+    filter: Option<Vec<FilterTerm>>,
     #[arg(short, long, default_value_t = false, requires("filter"))]
     /// Invert filter
     invert: bool,
     /// Only output number of matches
     #[arg(short, long)]
     count: bool,
+    /// Include archived repositories
+    #[arg(short, long)]
+    all: bool,
+    /// Show the number of open issues of each repository
+    #[arg(long)]
+    issues: bool,
 }
 
 fn main() {
     let args = Cli::parse();
 
-    let glob_set = match args.filter {
-        Some(set) => make_glob_set(set.iter()),
-        None => make_glob_set(iter::empty()),
-    };
+    let filter = RepoFilter::new(&args.filter.unwrap_or_default());
+    let show_archived = args.all || filter.selects_archived();
+    let repo_home = get_repo_home();
+    let username = get_username();
+    let mut roles = HashMap::new();
 
     let mut count: u32 = 0;
     let mut total_count: u32 = 0;
+    let mut hidden: u32 = 0;
 
     for path in filter_repos(None, false, |_| true) {
         total_count += 1;
 
-        if args.invert ^ (glob_set.is_empty() || glob_set.is_match(&path)) {
+        if args.invert ^ filter.matches(&path) {
+            let archived = is_archived(&repo_home.join(&path));
+            if archived && !show_archived {
+                hidden += 1;
+                continue;
+            }
             count += 1;
-            if !args.count {
-                println!("{}", path.display());
+            if args.count {
+                continue;
+            }
+            let mut notes = Vec::new();
+            if archived {
+                notes.push(String::from("archived"));
+            }
+            if let Some(org) = repo_org(&path) {
+                let role = roles
+                    .entry(org)
+                    .or_insert_with_key(|org| role(org, &username));
+                if *role == Some(Role::Read) {
+                    notes.push(String::from("read-only"));
+                }
+            }
+            if args.issues {
+                match open_issues(&repo_home.join(&path)) {
+                    0 => (),
+                    1 => notes.push(String::from("1 open issue")),
+                    n => notes.push(format!("{} open issues", n)),
+                }
+            }
+            match notes.is_empty() {
+                true => println!("{}", path.display()),
+                false => println!("{} ({})", path.display(), notes.join(", ")),
             }
         }
     }
@@ -50,4 +89,12 @@ fn main() {
         (0, _) => println!("Matched no repositories ({} total)", total_count),
         _ => println!("Matched {}/{} repositories", count, total_count),
     }
+    match hidden {
+        0 => (),
+        1 => println!("Hid 1 archived repository, use --all to show it"),
+        _ => println!(
+            "Hid {} archived repositories, use --all to show them",
+            hidden
+        ),
+    }
 }
@@ -0,0 +1,61 @@
+use clap::Parser;
+use commands::{git_command, parse_repo_path, parse_revision, parse_tree_path, repo_git_dir};
+use std::path::PathBuf;
+use std::process;
+
+/// Show the commit history of a repository
+#[derive(Parser)]
+#[command(about, arg_required_else_help = true)]
+struct Cli {
+    /// Path to repository
+    #[arg(value_parser = clap::builder::ValueParser::new(parse_repo_path))]
+    path: PathBuf,
+    /// Branch, tag or commit to start from
+    #[arg(default_value = "HEAD", value_parser = clap::builder::ValueParser::new(parse_revision))]
+    revision: String,
+    /// Number of commits to show
+    #[arg(short = 'n', long, default_value_t = 20, value_parser = clap::value_parser!(u32).range(1..=1000))]
+    count: u32,
+    /// Only show commits changing this file or folder
+    #[arg(long = "path", value_name = "PATH", value_parser = clap::builder::ValueParser::new(parse_tree_path))]
+    file_path: Option<PathBuf>,
+    /// Show the files changed by each commit
+    #[arg(short, long)]
+    stat: bool,
+}
+
+fn main() {
+    let args = Cli::parse();
+
+    let git_dir = repo_git_dir(&args.path).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+
+    let mut log = git_command(&git_dir);
+    log.args([
+        "--no-pager",
+        "log",
+        "--no-color",
+        "--no-ext-diff",
+        "--date=short",
+    ])
+    .arg("--format=%h %ad %<(16,trunc)%an %s")
+    .arg(format!("--max-count={}", args.count));
+    if args.stat {
+        log.arg("--stat");
+    }
+    log.arg("--end-of-options").arg(&args.revision).arg("--");
+    if let Some(file_path) = &args.file_path {
+        log.arg(file_path);
+    }
+
+    match log.status() {
+        Ok(status) if status.success() => (),
+        Ok(_) => process::exit(1),
+        Err(err) => {
+            eprintln!("Failed to read history: {}", err);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use clap::Parser;
+use commands::filter::{parse_repo_filter, FilterTerm, RepoFilter};
+use commands::stats::repo_stats;
+use commands::{all_bare_repos, get_repo_home, is_server, run_git, set_repo_option, unix_time};
+use std::path::Path;
+use std::process;
+use std::time::Instant;
+
+/// Run housekeeping on repositories
+#[derive(Parser)]
+#[command(about, arg_required_else_help = true)]
+struct Cli {
+    /// Paths to repositories, optionally narrowed by filters
+    #[arg(required_unless_present = "scheduled", num_args = 1.., value_parser = clap::builder::ValueParser::new(parse_repo_filter))]
+    path: Vec<FilterTerm>,
+    /// Optimise packs more thoroughly at the cost of time
+    #[arg(long)]
+    aggressive: bool,
+    /// Maintain every repository on the server
+    #[arg(long, hide = true, conflicts_with = "path")]
+    scheduled: bool,
+}
+
+fn main() {
+    let args = Cli::parse();
+
+    let repos = if args.scheduled {
+        if !is_server() {
+            eprintln!("Scheduled maintenance can only be run by the server");
+            process::exit(1);
+        }
+        all_bare_repos()
+    } else {
+        let repo_home = get_repo_home();
+        RepoFilter::new(&args.path)
+            .repos()
+            .into_iter()
+            .map(|path| (path.clone(), repo_home.join(path)))
+            .collect()
+    };
+
+    if repos.is_empty() {
+        eprintln!("No matching repositories found");
+        process::exit(1);
+    }
+
+    let mut failed = 0;
+    for (path, git_dir) in &repos {
+        let start = Instant::now();
+        let result = maintain(git_dir, args.aggressive).and_then(|_| match args.scheduled {
+            true => record_size(git_dir),
+            false => Ok(()),
+        });
+        match result {
+            Ok(()) => println!(
+                "'{}' : ok ({:.1}s)",
+                path.display(),
+                start.elapsed().as_secs_f32()
+            ),
+            Err(err) => {
+                eprintln!("'{}' : {}", path.display(), err);
+                failed += 1;
+            }
+        }
+    }
+
+    match (repos.len(), failed) {
+        (1, 0) => println!("Maintained 1 repository"),
+        (n, 0) => println!("Maintained {} repositories", n),
+        (n, _) => {
+            eprintln!("Maintained {}/{} repositories", n - failed, n);
+            process::exit(1);
+        }
+    }
+}
+
+fn maintain(git_dir: &Path, aggressive: bool) -> Result<(), String> {
+    let repack: &[&str] = match aggressive {
+        true => &[
+            "repack",
+            "-a",
+            "-d",
+            "-f",
+            "--depth=50",
+            "--window=250",
+            "-q",
+        ],
+        false => &["repack", "-a", "-d", "-q"],
+    };
+    let steps = [
+        repack,
+        &["prune", "--expire=2.weeks.ago"],
+        &["pack-refs", "--all", "--prune"],
+        &["commit-graph", "write", "--reachable"],
+    ];
+
+    let result = steps
+        .iter()
+        .try_for_each(|step| match run_git(git_dir, step) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(format!("{} failed: {}", step[0], err)),
+        });
+
+    let outcome = match &result {
+        Ok(()) => "ok",
+        Err(err) => err.as_str(),
+    };
+    set_repo_option(git_dir, "maintained", &unix_time().to_string())?;
+    set_repo_option(git_dir, "maintainResult", outcome)?;
+    result
+}
+
+/// Record the size of a repository, which `stats` shows growth against
+fn record_size(git_dir: &Path) -> Result<(), String> {
+    let stats = repo_stats(git_dir)?;
+    set_repo_option(git_dir, "size", &stats.size().to_string())?;
+    set_repo_option(git_dir, "sizeChecked", &unix_time().to_string())
+}
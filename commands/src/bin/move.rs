@@ -1,28 +1,45 @@
-use clap::Parser;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
+use commands::config::config;
+use commands::filter::{parse_repo_filter, FilterTerm, RepoFilter};
+use commands::orgs::repo_org;
+use commands::parse_repo_path_or_folder;
+use commands::webhooks::{notify, RepoEvent};
 use commands::{
     can_represent_repo, clean_empty_parent_folders, enforce_git_suffix, filter_repos,
-    get_repo_home, parse_repo_glob, represents_repo,
+    get_repo_home, parse_repo_glob, parse_repo_path, parse_repo_regex, represents_repo,
 };
-use commands::{make_glob_set, parse_repo_path_or_folder};
 use globset::Glob;
 use itertools::{Either, Itertools};
+use regex::Regex;
 use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::os::unix;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+/// Repositories of organisations are shared, and only ever created and deleted in place
+const ORG_MOVE_ERROR: &str = "Repositories of organisations cannot be moved";
 
 /// Rename a single or move multiple repositories
 #[derive(Parser)]
 #[command(about, arg_required_else_help = true)]
 struct Cli {
-    /// Paths to repositories
-    #[arg(num_args = 1.., value_parser = clap::builder::ValueParser::new(parse_repo_glob))]
-    source: Vec<Glob>,
+    /// Paths to repositories, optionally narrowed by filters such as `!dir/keep-*`, or a
+    /// regex when using --regex
+    #[arg(required = true, num_args = 1..)]
+    source: Vec<String>,
+    /// Only move repositories with a path matching this regex
+    #[arg(short, long, value_parser = clap::builder::ValueParser::new(parse_repo_regex))]
+    filter: Option<Regex>,
+    /// Skip repositories and folders matching this glob
+    #[arg(short, long, value_parser = clap::builder::ValueParser::new(parse_repo_glob))]
+    exclude: Vec<Glob>,
+    /// New path to repository, or a replacement when using --regex
+    destination: String,
+    /// Treat source as a regex and destination as its replacement, which can refer to
+    /// capture groups
     #[arg(short, long)]
-    filter: Option<String>,
-    /// New path to repository
-    #[arg(value_parser = clap::builder::ValueParser::new(parse_repo_path_or_folder))]
-    destination: PathBuf,
+    regex: bool,
     /// List changes without performing them
     #[arg(short, long)]
     dry_run: bool,
@@ -31,25 +48,90 @@ struct Cli {
 fn main() {
     let args = Cli::parse();
 
-    let glob_set = make_glob_set(args.source.iter());
-    let sources = filter_repos(None, true, |path| glob_set.is_match(path)).collect::<Vec<_>>();
+    if args.regex {
+        if args.source.len() > 1 {
+            Cli::command()
+                .error(
+                    ErrorKind::TooManyValues,
+                    "only one pattern can be used with --regex",
+                )
+                .exit();
+        }
+        let pattern = parse_repo_regex(&args.source[0])
+            .unwrap_or_else(|e| invalid_arg("<SOURCE>...", &args.source[0], &e));
+        let repo_filter = RepoFilter::new(&exclude_terms(&args));
+        let keep = keep_filter(&args, &repo_filter);
+        rename_regex(&pattern, &args.destination, args.dry_run, keep);
+        return;
+    }
+
+    let mut terms = args
+        .source
+        .iter()
+        .map(|s| parse_repo_filter(s).unwrap_or_else(|e| invalid_arg("<SOURCE>...", s, &e)))
+        .collect::<Vec<_>>();
+    terms.extend(exclude_terms(&args));
+    let destination = parse_repo_path_or_folder(&args.destination)
+        .unwrap_or_else(|e| invalid_arg("<DESTINATION>", &args.destination, &e));
+
+    let repo_filter = RepoFilter::new(&terms);
+    if !repo_filter.has_includes() {
+        invalid_arg("<SOURCE>...", "", "At least one path to move is required");
+    }
+    let keep = keep_filter(&args, &repo_filter);
+
+    let sources = filter_repos(None, true, |path| repo_filter.includes(path))
+        .filter(|path| !represents_repo(path) || keep(path))
+        .collect::<Vec<_>>();
 
     if sources.is_empty() {
         eprintln!("No matching repositories found");
         std::process::exit(1);
     }
 
+    if repo_org(&destination).is_some() || sources.iter().any(|src| repo_org(src).is_some()) {
+        eprintln!("{}", ORG_MOVE_ERROR);
+        std::process::exit(1);
+    }
+
     let rename_only =
-        sources.len() == 1 && represents_repo(&sources[0]) && can_represent_repo(&args.destination);
+        sources.len() == 1 && represents_repo(&sources[0]) && can_represent_repo(&destination);
 
     if rename_only {
-        move_single(&sources[0], &args.destination, args.dry_run);
+        move_single(&sources[0], &destination, args.dry_run);
     } else {
-        move_multiple(&sources, &args.destination, args.dry_run);
+        move_multiple(&sources, &destination, args.dry_run, keep);
     }
 }
 
-fn move_single(src: &PathBuf, dst: &PathBuf, dry_run: bool) {
+fn invalid_arg(name: &str, value: &str, err: &str) -> ! {
+    Cli::command()
+        .error(
+            ErrorKind::ValueValidation,
+            format!("invalid value '{}' for '{}': {}", value, name, err),
+        )
+        .exit()
+}
+
+fn exclude_terms(args: &Cli) -> Vec<FilterTerm> {
+    args.exclude
+        .iter()
+        .map(|glob| FilterTerm::Exclude(glob.clone()))
+        .collect()
+}
+
+/// Predicate for repositories passing `--filter` and accepted by the source filters
+fn keep_filter<'a>(args: &'a Cli, repo_filter: &'a RepoFilter) -> impl Fn(&Path) -> bool + 'a {
+    move |path: &Path| {
+        let included = match &args.filter {
+            Some(filter) => filter.is_match(path.to_str().unwrap()),
+            None => true,
+        };
+        included && repo_filter.accepts(path)
+    }
+}
+
+fn move_single(src: &Path, dst: &Path, dry_run: bool) {
     let dst = enforce_git_suffix(dst.to_path_buf()).unwrap();
 
     if src.eq(&dst) {
@@ -72,7 +154,7 @@ fn move_single(src: &PathBuf, dst: &PathBuf, dry_run: bool) {
     }
 
     let repo_home = get_repo_home();
-    let git_src = repo_home.join(&src);
+    let git_src = repo_home.join(src);
     let git_dst = repo_home.join(&dst);
 
     fs::create_dir_all(&git_dst).expect("Failed to create folders");
@@ -82,16 +164,20 @@ fn move_single(src: &PathBuf, dst: &PathBuf, dry_run: bool) {
     }
 
     fs::rename(&git_src, &git_dst).expect("Failed to move repository");
-    unix::fs::symlink(git_dst, dst).expect("Failed to link repo");
+    unix::fs::symlink(git_dst, &dst).expect("Failed to link repo");
 
-    fs::remove_file(&src).expect("Failed to unlink old location");
+    fs::remove_file(src).expect("Failed to unlink old location");
 
-    clean_empty_parent_folders(&src, None);
+    clean_empty_parent_folders(src, None);
     clean_empty_parent_folders(&git_src, Some(&repo_home));
     println!("Repository renamed");
+    notify(&dst, RepoEvent::Move(src));
 }
 
-fn move_multiple(sources: &Vec<PathBuf>, dst: &PathBuf, dry_run: bool) {
+fn move_multiple<F>(sources: &[PathBuf], dst: &Path, dry_run: bool, keep: F)
+where
+    F: Fn(&Path) -> bool,
+{
     if represents_repo(dst) {
         eprintln!("Destination is a repository, but multiple sources match");
         std::process::exit(1);
@@ -99,13 +185,13 @@ fn move_multiple(sources: &Vec<PathBuf>, dst: &PathBuf, dry_run: bool) {
 
     let moves = sources
         .iter()
-        .map(|path| {
+        .flat_map(|path| {
             if represents_repo(path) {
                 let name = path.file_name().unwrap().to_str().unwrap();
                 vec![(path.to_path_buf(), dst.join(name))]
             } else {
                 let path_parent = path.parent();
-                filter_repos(Some(path.to_owned()), false, |_| true)
+                filter_repos(Some(path.to_owned()), false, &keep)
                     .map(|src| {
                         let dst = if let Some(parent) = path_parent {
                             let clone = src.clone();
@@ -119,15 +205,60 @@ fn move_multiple(sources: &Vec<PathBuf>, dst: &PathBuf, dry_run: bool) {
                     .collect::<Vec<_>>()
             }
         })
-        .flatten()
         .filter(|(src, dst)| !src.eq(dst))
         .collect::<Vec<_>>();
 
+    perform_moves(moves, Vec::new(), dry_run);
+}
+
+fn rename_regex<F>(pattern: &Regex, replacement: &str, dry_run: bool, keep: F)
+where
+    F: Fn(&Path) -> bool,
+{
+    let sources = filter_repos(None, false, |path| {
+        keep(path) && pattern.is_match(path.to_str().unwrap())
+    })
+    .collect::<Vec<_>>();
+
+    if sources.is_empty() {
+        eprintln!("No matching repositories found");
+        std::process::exit(1);
+    }
+
+    let mut moves = Vec::new();
+    let mut invalid = Vec::new();
+    for src in sources {
+        let renamed = pattern
+            .replace(src.to_str().unwrap(), replacement)
+            .to_string();
+        match parse_repo_path(&renamed) {
+            Ok(dst) if dst.eq(&src) => (),
+            Ok(dst) if repo_org(&src).is_some() || repo_org(&dst).is_some() => {
+                invalid.push((src, dst, String::from(ORG_MOVE_ERROR)))
+            }
+            Ok(dst) => moves.push((src, dst)),
+            Err(err) => invalid.push((src, PathBuf::from(renamed), err)),
+        }
+    }
+
+    perform_moves(moves, invalid, dry_run);
+}
+
+/// Check a set of moves for conflicts, then perform them
+///
+/// Moves which can't be performed are listed alongside any `invalid` moves already
+/// found by the caller, in which case nothing is moved.
+fn perform_moves(
+    moves: Vec<(PathBuf, PathBuf)>,
+    invalid: Vec<(PathBuf, PathBuf, String)>,
+    dry_run: bool,
+) {
+    let max_depth = config().limits.max_depth;
     let mut tmp_required = false;
     let mut destinations_from: HashMap<PathBuf, PathBuf> = HashMap::new();
     let mut not_replaced: HashSet<&PathBuf> = HashSet::from_iter(moves.iter().map(|(src, _)| src));
 
-    let (moves, problems): (Vec<_>, Vec<_>) = moves.iter().partition_map(|(src, dst)| {
+    let (moves, mut problems): (Vec<_>, Vec<_>) = moves.iter().partition_map(|(src, dst)| {
         if dst.exists() {
             if !moves.iter().any(|(src, _)| src.eq(dst)) {
                 return Either::Right((
@@ -138,11 +269,11 @@ fn move_multiple(sources: &Vec<PathBuf>, dst: &PathBuf, dry_run: bool) {
             }
             tmp_required = true;
         }
-        if dst.components().count() > 4 {
+        if dst.components().count() > max_depth {
             return Either::Right((
                 src,
                 dst,
-                String::from("Destination has a nesting deeper than 4"),
+                format!("Destination has a nesting deeper than {}", max_depth),
             ));
         }
         if let Some(conflict) = destinations_from.get(dst) {
@@ -163,7 +294,13 @@ fn move_multiple(sources: &Vec<PathBuf>, dst: &PathBuf, dry_run: bool) {
         Either::Left((src, dst))
     });
 
-    if problems.len() > 0 {
+    problems.extend(
+        invalid
+            .iter()
+            .map(|(src, dst, err)| (src, dst, format!("Invalid destination : {}", err))),
+    );
+
+    if !problems.is_empty() {
         println!("Able to move {} repositories:", moves.len());
         for (src, dst) in moves {
             println!("'{}' -> '{}'", src.display(), dst.display());
@@ -201,12 +338,11 @@ fn move_multiple(sources: &Vec<PathBuf>, dst: &PathBuf, dry_run: bool) {
         return;
     }
 
-    const TMP_DIR: &str = ".tmp";
     let repo_home = get_repo_home();
-    let tmp_home = repo_home.join(TMP_DIR);
+    let tmp_home = repo_home.join(&config().tmp_dir);
 
     for (_, dst) in &moves {
-        let git_dst = repo_home.join(&dst);
+        let git_dst = repo_home.join(dst);
         fs::create_dir_all(&git_dst).expect("Failed to create folders");
     }
 
@@ -220,41 +356,41 @@ fn move_multiple(sources: &Vec<PathBuf>, dst: &PathBuf, dry_run: bool) {
         &repo_home
     } else {
         for (src, _) in &moves {
-            let tmp_git_dst = tmp_home.join(&src);
-            let git_dst = repo_home.join(&src);
+            let tmp_git_dst = tmp_home.join(src);
+            let git_dst = repo_home.join(src);
             fs::create_dir_all(&tmp_git_dst).expect("Failed to create folders");
             fs::create_dir_all(&git_dst).expect("Failed to create folders");
         }
         for (src, _) in &moves {
-            let git_src = repo_home.join(&src);
-            let tmp_git_dst = tmp_home.join(&src);
+            let git_src = repo_home.join(src);
+            let tmp_git_dst = tmp_home.join(src);
             fs::rename(&git_src, &tmp_git_dst).expect("Failed to move repository");
         }
         &tmp_home
     };
 
     for (src, dst) in &moves {
-        let git_src = repo_src.join(&src);
-        let git_dst = repo_home.join(&dst);
+        let git_src = repo_src.join(src);
+        let git_dst = repo_home.join(dst);
         println!("'{}' -> '{}'", src.display(), dst.display());
         fs::rename(&git_src, &git_dst).expect("Failed to move repository");
     }
 
     for (_, dst) in &moves {
-        let git_dst = repo_home.join(&dst);
+        let git_dst = repo_home.join(dst);
         if dst.is_symlink() {
-            fs::remove_file(&dst).expect("Failed to unlink old location");
+            fs::remove_file(dst).expect("Failed to unlink old location");
         }
         unix::fs::symlink(git_dst, dst).expect("Failed to link repo");
     }
 
     for src in &not_replaced {
-        fs::remove_file(&src).expect("Failed to unlink old location");
+        fs::remove_file(src).expect("Failed to unlink old location");
     }
 
     for src in &not_replaced {
-        let git_src = repo_home.join(&src);
-        clean_empty_parent_folders(&src, None);
+        let git_src = repo_home.join(src);
+        clean_empty_parent_folders(src, None);
         clean_empty_parent_folders(&git_src, None);
     }
 
@@ -263,4 +399,7 @@ fn move_multiple(sources: &Vec<PathBuf>, dst: &PathBuf, dry_run: bool) {
     }
 
     println!("Moved {} repositories", moves.len());
+    for (src, dst) in &moves {
+        notify(dst, RepoEvent::Move(src));
+    }
 }
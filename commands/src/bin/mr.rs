@@ -0,0 +1,473 @@
+use clap::{Args, Parser, Subcommand};
+use commands::config::config;
+use commands::events::{
+    append_event, event_ids, event_ref, parse_comment, parse_title, read_events, Event,
+};
+use commands::hooks::{check_update, post_receive, RefUpdate};
+use commands::orgs::{require_role, Role};
+use commands::stats::format_age;
+use commands::status::missing_statuses;
+use commands::{
+    default_branch, git_command, is_archived, parse_branch, parse_repo_path, repo_git_dir, run_git,
+    run_git_as_user,
+};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Refs holding the events of every merge request, numbered from 1
+const MR_PREFIX: &str = "refs/merge-requests";
+
+/// Review and merge branches of a repository
+#[derive(Parser)]
+#[command(about, arg_required_else_help = true)]
+struct Cli {
+    #[command(subcommand)]
+    action: Action,
+}
+
+#[derive(Subcommand)]
+enum Action {
+    /// Open a merge request for a branch
+    Open {
+        /// Path to repository
+        #[arg(value_parser = clap::builder::ValueParser::new(parse_repo_path))]
+        path: PathBuf,
+        /// Branch to merge
+        #[arg(value_parser = clap::builder::ValueParser::new(parse_branch))]
+        source: String,
+        /// Branch to merge into, the default branch if omitted
+        #[arg(value_parser = clap::builder::ValueParser::new(parse_branch))]
+        target: Option<String>,
+        /// Title of the merge request, the latest commit subject if omitted
+        #[arg(short, long, value_parser = clap::builder::ValueParser::new(parse_title))]
+        title: Option<String>,
+    },
+    /// List merge requests
+    List {
+        /// Path to repository
+        #[arg(value_parser = clap::builder::ValueParser::new(parse_repo_path))]
+        path: PathBuf,
+        /// Include merged and closed merge requests
+        #[arg(short, long)]
+        all: bool,
+    },
+    /// Show a merge request and its comments
+    Show(Target),
+    /// Show the changes a merge request would make
+    Diff {
+        #[command(flatten)]
+        target: Target,
+        /// Only show the files changed
+        #[arg(short, long)]
+        stat: bool,
+    },
+    /// Comment on a merge request
+    Comment {
+        #[command(flatten)]
+        target: Target,
+        /// Text of the comment
+        #[arg(value_parser = clap::builder::ValueParser::new(parse_comment))]
+        message: String,
+    },
+    /// Merge a merge request, fast-forwarding when possible
+    Merge {
+        #[command(flatten)]
+        target: Target,
+        /// Refuse to merge unless the target can be fast-forwarded
+        #[arg(long, conflicts_with = "no_ff")]
+        ff_only: bool,
+        /// Always create a merge commit
+        #[arg(long)]
+        no_ff: bool,
+    },
+    /// Close a merge request without merging it
+    Close(Target),
+}
+
+#[derive(Args)]
+struct Target {
+    /// Path to repository
+    #[arg(value_parser = clap::builder::ValueParser::new(parse_repo_path))]
+    path: PathBuf,
+    /// Number of the merge request
+    id: u64,
+}
+
+#[derive(PartialEq)]
+enum State {
+    Open,
+    Merged,
+    Closed,
+}
+
+impl State {
+    fn name(&self) -> &'static str {
+        match self {
+            State::Open => "open",
+            State::Merged => "merged",
+            State::Closed => "closed",
+        }
+    }
+}
+
+struct MergeRequest {
+    id: u64,
+    title: String,
+    source: String,
+    target: String,
+    state: State,
+    events: Vec<Event>,
+}
+
+impl MergeRequest {
+    fn load(git_dir: &Path, id: u64) -> Result<MergeRequest, String> {
+        let events = read_events(git_dir, &event_ref(MR_PREFIX, id))
+            .map_err(|_| format!("No merge request !{}", id))?;
+        let opened = events
+            .first()
+            .filter(|event| event.action() == "open")
+            .ok_or(format!("Merge request !{} is corrupt", id))?;
+        let field = |key| opened.field(key).unwrap_or_default().to_string();
+        let state = match events
+            .iter()
+            .rev()
+            .map(Event::action)
+            .find(|a| *a != "comment")
+        {
+            Some("merge") => State::Merged,
+            Some("close") => State::Closed,
+            _ => State::Open,
+        };
+
+        Ok(MergeRequest {
+            id,
+            title: field("title"),
+            source: field("source"),
+            target: field("target"),
+            state,
+            events,
+        })
+    }
+
+    fn comments(&self) -> usize {
+        self.events
+            .iter()
+            .filter(|e| e.action() == "comment")
+            .count()
+    }
+
+    fn merge_event(&self) -> Option<&Event> {
+        self.events.iter().rev().find(|e| e.action() == "merge")
+    }
+}
+
+fn main() {
+    let args = Cli::parse();
+
+    let path = match &args.action {
+        Action::Open { path, .. } | Action::List { path, .. } => path,
+        Action::Show(target) | Action::Close(target) => &target.path,
+        Action::Diff { target, .. }
+        | Action::Comment { target, .. }
+        | Action::Merge { target, .. } => &target.path,
+    };
+    let git_dir = repo_git_dir(path).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+
+    let read_only = matches!(
+        args.action,
+        Action::List { .. } | Action::Show(_) | Action::Diff { .. }
+    );
+    if !read_only && is_archived(&git_dir) {
+        eprintln!(
+            "'{}' : Repository is archived and read-only, unarchive it to change merge requests",
+            path.display()
+        );
+        process::exit(1);
+    }
+    if matches!(args.action, Action::Merge { .. } | Action::Close(_)) {
+        if let Err(err) = require_role(path, Role::Write) {
+            eprintln!("'{}' : {}", path.display(), err);
+            process::exit(1);
+        }
+    }
+
+    let result = match &args.action {
+        Action::Open {
+            source,
+            target,
+            title,
+            ..
+        } => open(&git_dir, source, target.as_deref(), title.as_deref()),
+        Action::List { all, .. } => list(&git_dir, *all),
+        Action::Show(target) => show(&git_dir, target.id),
+        Action::Diff { target, stat } => diff(&git_dir, target.id, *stat),
+        Action::Comment { target, message } => comment(&git_dir, target.id, message),
+        Action::Merge {
+            target,
+            ff_only,
+            no_ff,
+        } => merge(&git_dir, target.id, *ff_only, *no_ff),
+        Action::Close(target) => close(&git_dir, target.id),
+    };
+    if let Err(err) = result {
+        eprintln!("'{}' : {}", path.display(), err);
+        process::exit(1);
+    }
+}
+
+fn branch_commit(git_dir: &Path, branch: &str) -> Result<String, String> {
+    let reference = format!("refs/heads/{}", branch);
+    run_git(git_dir, &["rev-parse", "--verify", "--quiet", &reference])
+        .map(|sha| sha.trim().to_string())
+        .map_err(|_| format!("No branch named '{}'", branch))
+}
+
+fn open(
+    git_dir: &Path,
+    source: &str,
+    target: Option<&str>,
+    title: Option<&str>,
+) -> Result<(), String> {
+    let target = match target {
+        Some(target) => target.to_string(),
+        None => default_branch(git_dir).ok_or("Repository has no default branch")?,
+    };
+    if source == target {
+        return Err(String::from("Source and target branch are the same"));
+    }
+    let source_commit = branch_commit(git_dir, source)?;
+    branch_commit(git_dir, &target)?;
+
+    let title = match title {
+        Some(title) => title.to_string(),
+        None => run_git(git_dir, &["log", "-1", "--format=%s", &source_commit])?
+            .trim()
+            .to_string(),
+    };
+    let id = event_ids(git_dir, MR_PREFIX).last().unwrap_or(&0) + 1;
+    append_event(
+        git_dir,
+        &event_ref(MR_PREFIX, id),
+        &[
+            ("action", "open"),
+            ("source", source),
+            ("target", &target),
+            ("title", &title),
+        ],
+        "",
+    )?;
+    println!("Opened merge request !{} '{}' -> '{}'", id, source, target);
+    Ok(())
+}
+
+fn list(git_dir: &Path, all: bool) -> Result<(), String> {
+    let requests = event_ids(git_dir, MR_PREFIX)
+        .into_iter()
+        .filter_map(|id| MergeRequest::load(git_dir, id).ok())
+        .filter(|mr| all || mr.state == State::Open)
+        .collect::<Vec<_>>();
+    if requests.is_empty() {
+        println!("No merge requests found");
+        return Ok(());
+    }
+
+    println!(
+        "{:<5} {:<7} {:<32} {:<8} TITLE",
+        "ID", "STATE", "BRANCHES", "COMMENTS"
+    );
+    for mr in &requests {
+        println!(
+            "{:<5} {:<7} {:<32} {:<8} {}",
+            format!("!{}", mr.id),
+            mr.state.name(),
+            format!("{} -> {}", mr.source, mr.target),
+            mr.comments(),
+            mr.title
+        );
+    }
+    Ok(())
+}
+
+fn show(git_dir: &Path, id: u64) -> Result<(), String> {
+    let mr = MergeRequest::load(git_dir, id)?;
+    println!("!{} {}", mr.id, mr.title);
+    println!("'{}' -> '{}', {}", mr.source, mr.target, mr.state.name());
+
+    for event in &mr.events {
+        let when = format_age(event.time);
+        match event.action() {
+            "open" => println!("\nOpened by {} {}", event.author, when),
+            "comment" => {
+                println!("\n{} commented {}:", event.author, when);
+                for line in event.text.lines() {
+                    println!("    {}", line);
+                }
+            }
+            "merge" => println!(
+                "\nMerged by {} {} as {}",
+                event.author,
+                when,
+                event.field("commit").unwrap_or("?")
+            ),
+            "close" => println!("\nClosed by {} {}", event.author, when),
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+fn diff(git_dir: &Path, id: u64, stat: bool) -> Result<(), String> {
+    let mr = MergeRequest::load(git_dir, id)?;
+    // Merged branches may have moved on or been deleted, so show what was merged
+    let range = match mr.merge_event() {
+        Some(event) => format!(
+            "{}...{}",
+            event.field("target").unwrap_or_default(),
+            event.field("source").unwrap_or_default()
+        ),
+        None => format!(
+            "{}...{}",
+            branch_commit(git_dir, &mr.target)?,
+            branch_commit(git_dir, &mr.source)?
+        ),
+    };
+
+    let mut diff = git_command(git_dir);
+    diff.args([
+        "--no-pager",
+        "diff",
+        "--no-color",
+        "--no-ext-diff",
+        "--no-textconv",
+    ]);
+    if stat {
+        diff.arg("--stat");
+    }
+    let status = diff
+        .arg("--end-of-options")
+        .arg(&range)
+        .status()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(format!("Failed to show changes of !{}", id)),
+    }
+}
+
+fn comment(git_dir: &Path, id: u64, message: &str) -> Result<(), String> {
+    MergeRequest::load(git_dir, id)?;
+    append_event(
+        git_dir,
+        &event_ref(MR_PREFIX, id),
+        &[("action", "comment")],
+        message,
+    )?;
+    println!("Commented on merge request !{}", id);
+    Ok(())
+}
+
+fn merge(git_dir: &Path, id: u64, ff_only: bool, no_ff: bool) -> Result<(), String> {
+    let mr = MergeRequest::load(git_dir, id)?;
+    if mr.state != State::Open {
+        return Err(format!("Merge request !{} is {}", id, mr.state.name()));
+    }
+    let source = branch_commit(git_dir, &mr.source)?;
+    let target = branch_commit(git_dir, &mr.target)?;
+
+    let is_ancestor =
+        |a: &str, b: &str| run_git(git_dir, &["merge-base", "--is-ancestor", a, b]).is_ok();
+    if is_ancestor(&source, &target) {
+        return Err(format!("'{}' is already merged", mr.source));
+    }
+
+    let protection = &config().protection;
+    if protection.is_protected(&mr.target) {
+        let missing = missing_statuses(git_dir, &source, &protection.required_statuses);
+        if !missing.is_empty() {
+            return Err(format!(
+                "'{}' is protected and requires '{}' to succeed for {:.7} first",
+                mr.target,
+                missing.join("', '"),
+                source
+            ));
+        }
+    }
+
+    let fast_forward = !no_ff && is_ancestor(&target, &source);
+    let merged = if fast_forward {
+        source.clone()
+    } else if ff_only {
+        return Err(format!(
+            "'{}' cannot be fast-forwarded, rebase '{}' first",
+            mr.target, mr.source
+        ));
+    } else {
+        let tree = run_git(git_dir, &["merge-tree", "--write-tree", &target, &source])
+            .map_err(|_| String::from("Branches conflict, merge or rebase them locally first"))?;
+        let tree = tree.lines().next().unwrap_or_default();
+        let message = format!(
+            "Merge branch '{}' into {}\n\nMerge request !{}: {}",
+            mr.source, mr.target, id, mr.title
+        );
+        let commit = run_git_as_user(
+            git_dir,
+            &[
+                "commit-tree",
+                tree,
+                "-p",
+                &target,
+                "-p",
+                &source,
+                "-m",
+                &message,
+            ],
+        )?;
+        commit.trim().to_string()
+    };
+
+    // Moving the branch directly skips the hooks, so run the checks of a push first and
+    // notify others once it has moved
+    let update = RefUpdate {
+        old: target.clone(),
+        new: merged.clone(),
+        name: format!("refs/heads/{}", mr.target),
+    };
+    check_update(git_dir, &update, !fast_forward).map_err(|problems| problems.join("\n"))?;
+    run_git(git_dir, &["update-ref", &update.name, &merged, &target])?;
+    post_receive(git_dir, std::slice::from_ref(&update));
+    append_event(
+        git_dir,
+        &event_ref(MR_PREFIX, id),
+        &[
+            ("action", "merge"),
+            ("source", &source),
+            ("target", &target),
+            ("commit", &merged),
+        ],
+        "",
+    )?;
+
+    match fast_forward {
+        true => println!("Fast-forwarded '{}' to '{}'", mr.target, mr.source),
+        false => println!("Merged '{}' into '{}'", mr.source, mr.target),
+    }
+    Ok(())
+}
+
+fn close(git_dir: &Path, id: u64) -> Result<(), String> {
+    let mr = MergeRequest::load(git_dir, id)?;
+    if mr.state != State::Open {
+        return Err(format!("Merge request !{} is {}", id, mr.state.name()));
+    }
+    append_event(
+        git_dir,
+        &event_ref(MR_PREFIX, id),
+        &[("action", "close")],
+        "",
+    )?;
+    println!("Closed merge request !{}", id);
+    Ok(())
+}
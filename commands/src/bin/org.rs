@@ -0,0 +1,162 @@
+use clap::{Parser, Subcommand};
+use commands::orgs::{
+    change_members, create_org, org_dir, org_folder_name, parse_org, parse_role, request_change,
+    require_org_role, sync_all, sync_links, user_orgs, MemberChange, Role,
+};
+use commands::{bare_repos, get_username, is_server, parse_username, BASE_USER_PATH};
+use std::path::Path;
+use std::process;
+
+/// Manage organisations sharing repositories between their members
+#[derive(Parser)]
+#[command(about, arg_required_else_help = true)]
+struct Cli {
+    #[command(subcommand)]
+    action: Option<Action>,
+    /// Create an organisation owned by a user
+    #[arg(long, hide = true, requires = "owner", value_parser = clap::builder::ValueParser::new(parse_org))]
+    create: Option<String>,
+    #[arg(long, hide = true, value_parser = clap::builder::ValueParser::new(parse_username))]
+    owner: Option<String>,
+    /// Update the links and groups of every organisation's members
+    #[arg(long, hide = true, conflicts_with = "create")]
+    sync: bool,
+}
+
+#[derive(Subcommand)]
+enum Action {
+    /// List the organisations you are a member of
+    List,
+    /// List the members of an organisation
+    Members {
+        #[arg(value_parser = clap::builder::ValueParser::new(parse_org))]
+        org: String,
+    },
+    /// Add a member to an organisation, or change their role
+    Add {
+        #[arg(value_parser = clap::builder::ValueParser::new(parse_org))]
+        org: String,
+        #[arg(value_parser = clap::builder::ValueParser::new(parse_username))]
+        user: String,
+        /// Role of the member, one of read, write or owner
+        #[arg(short, long, default_value = "write", value_parser = clap::builder::ValueParser::new(parse_role))]
+        role: Role,
+    },
+    /// Remove a member from an organisation, or leave it yourself
+    Remove {
+        #[arg(value_parser = clap::builder::ValueParser::new(parse_org))]
+        org: String,
+        #[arg(value_parser = clap::builder::ValueParser::new(parse_username))]
+        user: String,
+    },
+}
+
+fn main() {
+    let args = Cli::parse();
+
+    if args.sync || args.create.is_some() {
+        if !is_server() {
+            eprintln!("Organisations can only be managed by the server");
+            process::exit(1);
+        }
+        let result = match (&args.create, &args.owner) {
+            (Some(org), Some(owner)) => create_org(org, owner)
+                .map(|_| println!("Created '{}' owned by '{}'", org_folder_name(org), owner)),
+            _ => sync_all(),
+        };
+        if let Err(err) = result {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let result = match args.action {
+        Some(Action::List) | None => list(),
+        Some(Action::Members { org }) => members(&org),
+        Some(Action::Add { org, user, role }) => add(&org, &user, role),
+        Some(Action::Remove { org, user }) => remove(&org, &user),
+    };
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+fn list() -> Result<(), String> {
+    let orgs = user_orgs(&get_username());
+    if orgs.is_empty() {
+        println!("You are not a member of any organisation");
+        return Ok(());
+    }
+    println!("{:<32} {:<6} REPOSITORIES", "ORGANISATION", "ROLE");
+    for (org, role) in orgs {
+        println!(
+            "{:<32} {:<6} {}",
+            org_folder_name(&org),
+            role.name(),
+            bare_repos(&org_dir(&org)).len()
+        );
+    }
+    Ok(())
+}
+
+fn members(org: &str) -> Result<(), String> {
+    let members = require_org_role(org, Role::Read)?;
+    println!("{:<32} ROLE", "MEMBER");
+    for (user, role) in &members.members {
+        println!("{:<32} {}", user, role.name());
+    }
+    Ok(())
+}
+
+fn add(org: &str, user: &str, role: Role) -> Result<(), String> {
+    require_org_role(org, Role::Owner)?;
+    if !Path::new(BASE_USER_PATH).join(user).is_dir() {
+        return Err(format!("No user '{}'", user));
+    }
+    change(MemberChange {
+        org: org.to_string(),
+        user: user.to_string(),
+        role: Some(role),
+    })
+}
+
+fn remove(org: &str, user: &str) -> Result<(), String> {
+    let required = match user == get_username() {
+        true => Role::Read,
+        false => Role::Owner,
+    };
+    let members = require_org_role(org, required)?;
+    if !members.members.contains_key(user) {
+        return Err(format!(
+            "'{}' is not a member of '{}'",
+            user,
+            org_folder_name(org)
+        ));
+    }
+    change(MemberChange {
+        org: org.to_string(),
+        user: user.to_string(),
+        role: None,
+    })
+}
+
+/// Change members right away when run by the server, otherwise ask the server to shortly
+fn change(change: MemberChange) -> Result<(), String> {
+    if !is_server() {
+        request_change(change)?;
+        println!("Members are updated within a minute");
+        return Ok(());
+    }
+    println!("{}", change_members(&get_username(), &change)?);
+    sync_member(&change.user);
+    Ok(())
+}
+
+/// Update the links of a member right away if possible, otherwise the server does so shortly
+fn sync_member(user: &str) {
+    if sync_links(user).is_err() {
+        println!("Access of '{}' is updated within a minute", user);
+    }
+}
@@ -0,0 +1,134 @@
+use clap::{Parser, Subcommand};
+use commands::config::config;
+use commands::orgs::ORG_PREFIX;
+use commands::parse_repo_glob;
+use commands::policies::{
+    load_policies, parse_domain, parse_message_pattern, parse_path_glob, save_policies, Policy,
+};
+use std::process;
+
+/// Enforce rules for commit messages, authors and paths on pushes
+#[derive(Parser)]
+#[command(about, arg_required_else_help = true)]
+struct Cli {
+    #[command(subcommand)]
+    action: Action,
+}
+
+#[derive(Subcommand)]
+enum Action {
+    /// List policies, including those set by the server
+    List,
+    /// Add a policy for your repositories matching a glob
+    Add {
+        /// Repositories the policy applies to, all of your own if omitted
+        #[arg(default_value = ".", value_parser = clap::builder::ValueParser::new(parse_glob))]
+        glob: String,
+        /// Pattern every commit message must match
+        #[arg(short, long, value_parser = clap::builder::ValueParser::new(parse_message_pattern))]
+        message_pattern: Option<String>,
+        /// Longest the first line of a commit message may be
+        #[arg(short = 'l', long, value_parser = clap::value_parser!(u64).range(1..))]
+        max_subject_length: Option<u64>,
+        /// Domain author and committer emails must belong to, repeatable
+        #[arg(short, long = "email-domain", value_parser = clap::builder::ValueParser::new(parse_domain))]
+        email_domains: Vec<String>,
+        /// Glob of files commits cannot add or change, repeatable
+        #[arg(short, long = "forbidden-path", value_parser = clap::builder::ValueParser::new(parse_path_glob))]
+        forbidden_paths: Vec<String>,
+    },
+    /// Remove a policy
+    Remove {
+        /// Number of the policy, as shown by list
+        id: usize,
+    },
+}
+
+fn parse_glob(glob: &str) -> Result<String, String> {
+    if glob.starts_with(ORG_PREFIX) {
+        return Err(String::from(
+            "Policies for organisations can only be set by administrators",
+        ));
+    }
+    parse_repo_glob(glob).map(|_| glob.to_string())
+}
+
+fn main() {
+    let args = Cli::parse();
+
+    let mut policies = load_policies().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+
+    match args.action {
+        Action::List => {
+            let server = &config().policies;
+            if policies.is_empty() && server.is_empty() {
+                println!("No policies added");
+                return;
+            }
+            println!("{:<3} {:<24} RULES", "ID", "REPOSITORIES");
+            for policy in server {
+                println!(
+                    "{:<3} {:<24} {} (set by the server)",
+                    "-",
+                    policy.glob,
+                    policy.rules().join("; ")
+                );
+            }
+            for (i, policy) in policies.iter().enumerate() {
+                println!(
+                    "{:<3} {:<24} {}",
+                    i + 1,
+                    policy.glob,
+                    policy.rules().join("; ")
+                );
+            }
+        }
+        Action::Add {
+            glob,
+            message_pattern,
+            max_subject_length,
+            email_domains,
+            forbidden_paths,
+        } => {
+            let policy = Policy {
+                glob,
+                message_pattern,
+                max_subject_length: max_subject_length.map(|length| length as usize),
+                email_domains,
+                forbidden_paths,
+            };
+            if policy.rules().is_empty() {
+                eprintln!("A policy needs at least one rule");
+                process::exit(1);
+            }
+            let message = format!(
+                "Added policy {} for '{}' : {}",
+                policies.len() + 1,
+                policy.glob,
+                policy.rules().join("; ")
+            );
+            policies.push(policy);
+            save(&policies);
+            println!("{}", message);
+        }
+        Action::Remove { id } => {
+            if id == 0 || id > policies.len() {
+                eprintln!("No policy {}", id);
+                process::exit(1);
+            }
+            let policy = policies.remove(id - 1);
+            save(&policies);
+            println!("Removed policy for '{}'", policy.glob);
+        }
+    }
+}
+
+fn save(policies: &[Policy]) {
+    save_policies(policies).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+}
@@ -0,0 +1,62 @@
+use clap::Parser;
+use commands::{git_command, parse_repo_path, parse_revision, parse_tree_path, repo_git_dir};
+use std::path::PathBuf;
+use std::process;
+
+/// Show a commit, or a file or folder at a revision
+#[derive(Parser)]
+#[command(about, arg_required_else_help = true)]
+struct Cli {
+    /// Path to repository
+    #[arg(value_parser = clap::builder::ValueParser::new(parse_repo_path))]
+    path: PathBuf,
+    /// Revision to show, optionally followed by ':' and a path inside the repository
+    #[arg(default_value = "HEAD", value_parser = clap::builder::ValueParser::new(parse_object))]
+    object: String,
+    /// Only show the files changed by a commit
+    #[arg(short, long)]
+    stat: bool,
+}
+
+fn parse_object(object: &str) -> Result<String, String> {
+    match object.split_once(':') {
+        Some((revision, "")) => Ok(format!("{}:", parse_revision(revision)?)),
+        Some((revision, path)) => {
+            let revision = parse_revision(revision)?;
+            let path = parse_tree_path(path)?;
+            Ok(format!("{}:{}", revision, path.display()))
+        }
+        None => parse_revision(object),
+    }
+}
+
+fn main() {
+    let args = Cli::parse();
+
+    let git_dir = repo_git_dir(&args.path).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+
+    let mut show = git_command(&git_dir);
+    show.args([
+        "--no-pager",
+        "show",
+        "--no-color",
+        "--no-ext-diff",
+        "--no-textconv",
+    ]);
+    if args.stat {
+        show.arg("--stat");
+    }
+    show.arg("--end-of-options").arg(&args.object);
+
+    match show.status() {
+        Ok(status) if status.success() => (),
+        Ok(_) => process::exit(1),
+        Err(err) => {
+            eprintln!("Failed to show '{}': {}", args.object, err);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use clap::{Parser, ValueEnum};
+use commands::{
+    git_command, parse_repo_path, parse_revision, parse_tree_path, repo_git_dir, run_git,
+};
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+use std::process;
+
+/// Download an archive of a repository at any revision
+#[derive(Parser)]
+#[command(about, arg_required_else_help = true)]
+struct Cli {
+    /// Path to repository
+    #[arg(value_parser = clap::builder::ValueParser::new(parse_repo_path))]
+    path: PathBuf,
+    /// Branch, tag or commit to archive
+    #[arg(default_value = "HEAD", value_parser = clap::builder::ValueParser::new(parse_revision))]
+    revision: String,
+    /// Only archive this folder of the repository
+    #[arg(value_parser = clap::builder::ValueParser::new(parse_tree_path))]
+    folder: Option<PathBuf>,
+    /// Archive format
+    #[arg(short, long, value_enum, default_value_t = Format::TarGz)]
+    format: Format,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    #[value(name = "tar.gz")]
+    TarGz,
+    Tar,
+    Zip,
+}
+
+impl Format {
+    fn name(self) -> &'static str {
+        match self {
+            Format::TarGz => "tar.gz",
+            Format::Tar => "tar",
+            Format::Zip => "zip",
+        }
+    }
+}
+
+fn main() {
+    let args = Cli::parse();
+
+    if io::stdout().is_terminal() {
+        eprintln!("Refusing to write an archive to a terminal, redirect the output to a file");
+        process::exit(1);
+    }
+
+    let git_dir = repo_git_dir(&args.path).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+
+    let tree = match &args.folder {
+        Some(folder) => format!("{}:{}", args.revision, folder.display()),
+        None => args.revision.clone(),
+    };
+    let tree_ish = run_git(
+        &git_dir,
+        &[
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            "--end-of-options",
+            &tree,
+        ],
+    )
+    .and_then(|object| run_git(&git_dir, &["cat-file", "-t", object.trim()]));
+    if !tree_ish.is_ok_and(|kind| matches!(kind.trim(), "tree" | "commit" | "tag")) {
+        match &args.folder {
+            Some(folder) => eprintln!(
+                "No folder '{}' at revision '{}'",
+                folder.display(),
+                args.revision
+            ),
+            None => eprintln!("Unknown revision '{}'", args.revision),
+        }
+        process::exit(1);
+    }
+
+    let name = args.path.file_stem().unwrap().to_string_lossy();
+    let label = args.revision.replace(['/', ':', '~', '^'], "-");
+    let status = git_command(&git_dir)
+        .arg("archive")
+        .arg(format!("--format={}", args.format.name()))
+        .arg(format!("--prefix={}-{}/", name, label))
+        .arg("--end-of-options")
+        .arg(&tree)
+        .status();
+
+    match status {
+        Ok(status) if status.success() => (),
+        Ok(status) => process::exit(status.code().unwrap_or(1)),
+        Err(err) => {
+            eprintln!("Failed to create archive: {}", err);
+            process::exit(1);
+        }
+    }
+}
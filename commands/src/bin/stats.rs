@@ -0,0 +1,133 @@
+use clap::{Parser, ValueEnum};
+use commands::filter::{parse_repo_filter, FilterTerm, RepoFilter};
+use commands::stats::{format_size, repo_stats, RepoStats};
+use commands::{get_repo_home, get_repo_option};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Show disk usage of repositories
+#[derive(Parser)]
+#[command(about)]
+struct Cli {
+    /// Filters to apply to results
+    #[arg(num_args = 0.., value_parser = clap::builder::ValueParser::new(parse_repo_filter))]
+    filter: Option<Vec<FilterTerm>>,
+    /// Total usage per folder instead of per repository, with repositories outside any
+    /// folder counted under `./`
+    #[arg(short, long)]
+    folders: bool,
+    /// Order of results
+    #[arg(short, long, value_enum, default_value_t = Sort::Size)]
+    sort: Sort,
+    /// Only show the largest results
+    #[arg(short = 'n', long)]
+    limit: Option<usize>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Sort {
+    Size,
+    Growth,
+    Name,
+}
+
+struct Usage {
+    stats: RepoStats,
+    growth: Option<i64>,
+}
+
+fn main() {
+    let args = Cli::parse();
+
+    let paths = RepoFilter::new(args.filter.as_deref().unwrap_or_default()).repos();
+    if paths.is_empty() {
+        eprintln!("No matching repositories found");
+        process::exit(1);
+    }
+
+    let repo_home = get_repo_home();
+    let mut usages: BTreeMap<PathBuf, Usage> = BTreeMap::new();
+    let mut total = RepoStats::default();
+
+    for path in paths {
+        let git_dir = repo_home.join(&path);
+        let stats = match repo_stats(&git_dir) {
+            Ok(stats) => stats,
+            Err(err) => {
+                eprintln!("'{}' : {}", path.display(), err);
+                continue;
+            }
+        };
+
+        // Sizes are recorded by scheduled maintenance, so growth is since it last ran
+        let previous = get_repo_option(&git_dir, "size").and_then(|s| s.parse::<u64>().ok());
+        let growth = previous.map(|previous| stats.size() as i64 - previous as i64);
+        total.add(&stats);
+
+        if !args.folders {
+            usages.insert(path, Usage { stats, growth });
+            continue;
+        }
+        for folder in path.ancestors().skip(1) {
+            // Only repositories outside any folder count towards the top level
+            let folder = match folder.as_os_str().is_empty() {
+                true if path.parent() == Some(folder) => Path::new("."),
+                true => continue,
+                false => folder,
+            };
+            let usage = usages.entry(folder.to_path_buf()).or_insert(Usage {
+                stats: RepoStats::default(),
+                growth: Some(0),
+            });
+            usage.stats.add(&stats);
+            usage.growth = usage.growth.zip(growth).map(|(a, b)| a + b);
+        }
+    }
+
+    let mut usages = usages.into_iter().collect::<Vec<_>>();
+    match args.sort {
+        Sort::Size => usages.sort_by_key(|(_, usage)| u64::MAX - usage.stats.size()),
+        Sort::Growth => usages.sort_by_key(|(_, usage)| -usage.growth.unwrap_or(0)),
+        Sort::Name => (),
+    }
+    if let Some(limit) = args.limit {
+        usages.truncate(limit);
+    }
+
+    let name = if args.folders { "FOLDER" } else { "REPOSITORY" };
+    println!(
+        "{:<10} {:<10} {:<10} {:<10} {:<8} {:<11} {}",
+        "SIZE", "LOOSE", "PACKED", "LFS", "OBJECTS", "GROWTH", name
+    );
+    for (path, usage) in &usages {
+        let stats = &usage.stats;
+        let lfs = match stats.lfs_objects {
+            0 => String::from("-"),
+            _ => format_size(stats.lfs_size),
+        };
+        let growth = match usage.growth {
+            None => String::from("new"),
+            Some(0) => String::from("-"),
+            Some(g) if g > 0 => format!("+{}", format_size(g as u64)),
+            Some(g) => format!("-{}", format_size(g.unsigned_abs())),
+        };
+        let suffix = if args.folders { "/" } else { "" };
+        println!(
+            "{:<10} {:<10} {:<10} {:<10} {:<8} {:<11} {}{}",
+            format_size(stats.size()),
+            format_size(stats.loose_size),
+            format_size(stats.packed_size),
+            lfs,
+            stats.objects(),
+            growth,
+            path.display(),
+            suffix
+        );
+    }
+    println!(
+        "Total {} in {} objects",
+        format_size(total.size()),
+        total.objects()
+    );
+}
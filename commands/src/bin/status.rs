@@ -0,0 +1,128 @@
+use clap::{Args, Parser, Subcommand};
+use commands::orgs::{require_role, Role};
+use commands::stats::format_age;
+use commands::status::{parse_context, parse_state, set_status, statuses, StatusState};
+use commands::webhooks::parse_url;
+use commands::{is_archived, parse_repo_path, parse_revision, repo_git_dir, run_git};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Report and list the statuses of commits, such as CI results
+#[derive(Parser)]
+#[command(about, arg_required_else_help = true)]
+struct Cli {
+    #[command(subcommand)]
+    action: Action,
+}
+
+#[derive(Subcommand)]
+enum Action {
+    /// Set the status of a context for a commit
+    Set {
+        #[command(flatten)]
+        target: Target,
+        /// Name of the check reporting the status, such as 'lint', other than the server's 'ci'
+        #[arg(value_parser = clap::builder::ValueParser::new(parse_context))]
+        context: String,
+        /// One of 'success', 'failure' or 'pending'
+        #[arg(value_parser = clap::builder::ValueParser::new(parse_state))]
+        state: StatusState,
+        /// Link to details, such as the build log
+        #[arg(value_parser = clap::builder::ValueParser::new(parse_url))]
+        url: Option<String>,
+    },
+    /// List the statuses of a commit
+    List(Target),
+}
+
+#[derive(Args)]
+struct Target {
+    /// Path to repository
+    #[arg(value_parser = clap::builder::ValueParser::new(parse_repo_path))]
+    path: PathBuf,
+    /// Commit, branch or tag
+    #[arg(value_parser = clap::builder::ValueParser::new(parse_revision))]
+    revision: String,
+}
+
+fn main() {
+    let args = Cli::parse();
+
+    let target = match &args.action {
+        Action::Set { target, .. } | Action::List(target) => target,
+    };
+    let git_dir = repo_git_dir(&target.path).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+    if matches!(args.action, Action::Set { .. }) {
+        if let Err(err) = require_role(&target.path, Role::Write) {
+            eprintln!("'{}' : {}", target.path.display(), err);
+            process::exit(1);
+        }
+    }
+    if matches!(args.action, Action::Set { .. }) && is_archived(&git_dir) {
+        eprintln!(
+            "'{}' : Repository is archived and read-only, unarchive it to set statuses",
+            target.path.display()
+        );
+        process::exit(1);
+    }
+
+    let result = resolve_commit(&git_dir, &target.revision).and_then(|commit| match &args.action {
+        Action::Set {
+            context,
+            state,
+            url,
+            ..
+        } => set_status(&git_dir, &commit, context, *state, url.as_deref())
+            .map(|_| println!("Set '{}' to {} for {:.7}", context, state.name(), commit)),
+        Action::List(_) => {
+            list(&git_dir, &commit);
+            Ok(())
+        }
+    });
+    if let Err(err) = result {
+        eprintln!("'{}' : {}", target.path.display(), err);
+        process::exit(1);
+    }
+}
+
+fn resolve_commit(git_dir: &Path, revision: &str) -> Result<String, String> {
+    let commit = format!("{}^{{commit}}", revision);
+    run_git(
+        git_dir,
+        &[
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            "--end-of-options",
+            &commit,
+        ],
+    )
+    .map(|sha| sha.trim().to_string())
+    .map_err(|_| format!("No commit named '{}'", revision))
+}
+
+fn list(git_dir: &Path, commit: &str) {
+    let statuses = statuses(git_dir, commit);
+    if statuses.is_empty() {
+        println!("No statuses for {:.7}", commit);
+        return;
+    }
+
+    println!(
+        "{:<16} {:<8} {:<16} {:<16} URL",
+        "CONTEXT", "STATE", "BY", "UPDATED"
+    );
+    for status in &statuses {
+        println!(
+            "{:<16} {:<8} {:<16} {:<16} {}",
+            status.context,
+            status.state.name(),
+            status.user,
+            format_age(status.time),
+            status.url.as_deref().unwrap_or("-")
+        );
+    }
+}
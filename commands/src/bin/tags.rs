@@ -0,0 +1,55 @@
+use clap::Parser;
+use commands::{parse_repo_path, repo_git_dir, run_git};
+use std::path::PathBuf;
+use std::process;
+
+/// List the tags of a repository
+#[derive(Parser)]
+#[command(about, arg_required_else_help = true)]
+struct Cli {
+    /// Path to repository
+    #[arg(value_parser = clap::builder::ValueParser::new(parse_repo_path))]
+    path: PathBuf,
+}
+
+fn main() {
+    let args = Cli::parse();
+
+    let git_dir = repo_git_dir(&args.path).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+
+    let tags = run_git(
+        &git_dir,
+        &[
+            "for-each-ref",
+            "--sort=-creatordate",
+            "--format=%(refname:short)%00%(objectname:short)%00%(*objectname:short)%00%(creatordate:short)%00%(subject)",
+            "refs/tags/",
+        ],
+    )
+    .unwrap_or_else(|err| {
+        eprintln!("'{}' : {}", args.path.display(), err);
+        process::exit(1);
+    });
+
+    if tags.trim().is_empty() {
+        println!("'{}' has no tags", args.path.display());
+        return;
+    }
+
+    println!("{:<24} {:<9} {:<10} SUBJECT", "TAG", "COMMIT", "DATE");
+    for line in tags.lines() {
+        let fields = line.split('\0').collect::<Vec<_>>();
+        let [name, object, target, date, subject] = fields[..] else {
+            continue;
+        };
+        // Annotated tags point to a tag object, show the commit it refers to instead
+        let commit = match target {
+            "" => object,
+            target => target,
+        };
+        println!("{:<24} {:<9} {:<10} {}", name, commit, date, subject);
+    }
+}
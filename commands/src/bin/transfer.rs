@@ -0,0 +1,352 @@
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
+use commands::config::config;
+use commands::filter::{parse_repo_filter, RepoFilter};
+use commands::orgs::repo_org;
+use commands::transfers::{
+    all_users, can_transfer, load_transfers, offers_to, save_transfers, transfer_repo, Acceptance,
+    Offer,
+};
+use commands::{
+    bare_repos, get_username, is_server, parse_repo_glob, parse_repo_path_or_folder,
+    parse_username, BASE_USER_PATH,
+};
+use globset::GlobMatcher;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Transfer repositories to another user, who has to accept them
+#[derive(Parser)]
+#[command(about, arg_required_else_help = true)]
+struct Cli {
+    /// Paths to repositories, optionally narrowed by filters, followed by the user to offer
+    /// them to. When accepting or cancelling, globs selecting the offered repositories
+    #[arg(value_name = "PATH")]
+    args: Vec<String>,
+    /// List the transfers offered by and to you
+    #[arg(short, long, group = "mode")]
+    list: bool,
+    /// Accept the repositories offered by a user
+    #[arg(short, long, group = "mode", value_name = "USER", value_parser = clap::builder::ValueParser::new(parse_username))]
+    accept: Option<String>,
+    /// Cancel the offers you made to a user
+    #[arg(short, long, group = "mode", value_name = "USER", value_parser = clap::builder::ValueParser::new(parse_username))]
+    cancel: Option<String>,
+    /// Folder to place accepted repositories in
+    #[arg(short, long, value_name = "FOLDER", value_parser = clap::builder::ValueParser::new(parse_folder))]
+    into: Option<PathBuf>,
+    /// Perform the transfers accepted by every user
+    #[arg(long, hide = true, group = "mode")]
+    scheduled: bool,
+    /// Transfer every repository of a user without asking
+    #[arg(long, hide = true, group = "mode", requires = "to", value_parser = clap::builder::ValueParser::new(parse_username))]
+    from: Option<String>,
+    #[arg(long, hide = true, value_parser = clap::builder::ValueParser::new(parse_username))]
+    to: Option<String>,
+}
+
+fn parse_folder(folder: &str) -> Result<PathBuf, String> {
+    let folder = parse_repo_path_or_folder(folder)?;
+    match repo_org(&folder) {
+        Some(_) => Err(String::from(
+            "Repositories cannot be transferred to organisations",
+        )),
+        None => Ok(folder),
+    }
+}
+
+fn main() {
+    let args = Cli::parse();
+
+    if args.scheduled || args.from.is_some() {
+        if !is_server() {
+            eprintln!("Transfers can only be forced by the server");
+            process::exit(1);
+        }
+        match (&args.from, &args.to) {
+            (Some(from), Some(to)) => transfer_all(from, to, args.into.as_deref()),
+            _ => run_scheduled(),
+        }
+        return;
+    }
+
+    let result = if args.list {
+        list()
+    } else if let Some(from) = &args.accept {
+        accept(from, &matchers(&args.args), args.into.as_deref())
+    } else if let Some(to) = &args.cancel {
+        cancel(to, &matchers(&args.args))
+    } else {
+        offer(&args.args)
+    };
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+fn invalid_arg(name: &str, value: &str, err: &str) -> ! {
+    Cli::command()
+        .error(
+            ErrorKind::ValueValidation,
+            format!("invalid value '{}' for '{}': {}", value, name, err),
+        )
+        .exit()
+}
+
+fn matchers(globs: &[String]) -> Vec<GlobMatcher> {
+    globs
+        .iter()
+        .map(|glob| match parse_repo_glob(glob) {
+            Ok(glob) => glob.compile_matcher(),
+            Err(err) => invalid_arg("<PATH>...", glob, &err),
+        })
+        .collect()
+}
+
+fn selected(matchers: &[GlobMatcher], path: &Path) -> bool {
+    matchers.is_empty()
+        || path
+            .ancestors()
+            .any(|p| matchers.iter().any(|m| m.is_match(p)))
+}
+
+fn offer(args: &[String]) -> Result<(), String> {
+    let Some((user, sources)) = args.split_last().filter(|(_, sources)| !sources.is_empty()) else {
+        Cli::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "the repositories to transfer and the user to offer them to are required",
+            )
+            .exit()
+    };
+    let user = parse_username(user).unwrap_or_else(|e| invalid_arg("<USER>", user, &e));
+    let terms = sources
+        .iter()
+        .map(|s| parse_repo_filter(s).unwrap_or_else(|e| invalid_arg("<PATH>...", s, &e)))
+        .collect::<Vec<_>>();
+
+    let username = get_username();
+    if user == username {
+        return Err(String::from("Cannot transfer repositories to yourself"));
+    }
+    if !Path::new(BASE_USER_PATH).join(&user).is_dir() {
+        return Err(format!("No user '{}'", user));
+    }
+    let paths = RepoFilter::new(&terms)
+        .repos()
+        .into_iter()
+        .filter(|path| repo_org(path).is_none())
+        .collect::<Vec<_>>();
+    if paths.is_empty() {
+        return Err(String::from("No matching repositories found"));
+    }
+
+    let mut transfers = load_transfers(&username)?;
+    for path in &paths {
+        transfers.offer.retain(|offer| offer.path != *path);
+        transfers.offer.push(Offer::new(path, &user));
+        println!("Offered '{}' to '{}'", path.display(), user);
+    }
+    save_transfers(&username, &transfers)?;
+    println!(
+        "'{}' can accept them with 'transfer --accept {}'",
+        user, username
+    );
+    Ok(())
+}
+
+fn list() -> Result<(), String> {
+    let username = get_username();
+    let transfers = load_transfers(&username)?;
+    let incoming = offers_to(&username);
+    if transfers.offer.is_empty() && incoming.is_empty() {
+        println!("No transfers offered by or to you");
+        return Ok(());
+    }
+
+    println!("{:<8} {:<16} REPOSITORY", "TRANSFER", "USER");
+    for offer in &transfers.offer {
+        println!("{:<8} {:<16} {}", "to", offer.to, offer.path.display());
+    }
+    for (from, offer) in &incoming {
+        let accepted = transfers
+            .accept
+            .iter()
+            .find(|a| a.from == *from && a.path == offer.path);
+        match accepted {
+            Some(a) => println!(
+                "{:<8} {:<16} {} (accepted as '{}')",
+                "from",
+                from,
+                offer.path.display(),
+                a.destination.display()
+            ),
+            None => println!("{:<8} {:<16} {}", "from", from, offer.path.display()),
+        }
+    }
+    Ok(())
+}
+
+fn accept(from: &str, matchers: &[GlobMatcher], into: Option<&Path>) -> Result<(), String> {
+    let username = get_username();
+    let offers = load_transfers(from)?
+        .offer
+        .into_iter()
+        .filter(|offer| offer.to == username && selected(matchers, &offer.path))
+        .collect::<Vec<_>>();
+    if offers.is_empty() {
+        return Err(format!("No matching repositories offered by '{}'", from));
+    }
+
+    let mut transfers = load_transfers(&username)?;
+    let mut failed = false;
+    for offer in offers {
+        let destination = into.unwrap_or(Path::new("")).join(&offer.path);
+        if destination.exists() || destination.is_symlink() {
+            eprintln!("'{}' : Destination occupied", destination.display());
+            failed = true;
+            continue;
+        }
+        if !can_transfer() {
+            transfers
+                .accept
+                .retain(|a| a.from != from || a.path != offer.path);
+            transfers.accept.push(Acceptance {
+                from: from.to_string(),
+                path: offer.path.clone(),
+                destination: destination.clone(),
+            });
+            println!(
+                "Accepted '{}', it is moved to '{}' within a minute",
+                offer.path.display(),
+                destination.display()
+            );
+            continue;
+        }
+        match complete(from, &offer.path, &username, &destination) {
+            Ok(()) => println!("Transferred '{}'", destination.display()),
+            Err(err) => {
+                eprintln!("'{}' : {}", offer.path.display(), err);
+                failed = true;
+            }
+        }
+    }
+    save_transfers(&username, &transfers)?;
+    if failed {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+fn cancel(to: &str, matchers: &[GlobMatcher]) -> Result<(), String> {
+    let username = get_username();
+    let mut transfers = load_transfers(&username)?;
+    let before = transfers.offer.len();
+    transfers
+        .offer
+        .retain(|offer| offer.to != to || !selected(matchers, &offer.path));
+    let cancelled = before - transfers.offer.len();
+    save_transfers(&username, &transfers)?;
+    match cancelled {
+        0 => return Err(format!("No matching offers to '{}'", to)),
+        1 => println!("Cancelled 1 offer"),
+        n => println!("Cancelled {} offers", n),
+    }
+    Ok(())
+}
+
+/// Move a repository to the receiver, and remove any offers made for it
+fn complete(from: &str, path: &Path, to: &str, destination: &Path) -> Result<(), String> {
+    transfer_repo(from, path, to, destination)?;
+    let mut transfers = load_transfers(from)?;
+    transfers.offer.retain(|offer| offer.path != path);
+    save_transfers(from, &transfers)
+}
+
+/// Perform the transfers receivers have accepted since the last run
+fn run_scheduled() {
+    for user in all_users() {
+        let Ok(mut transfers) = load_transfers(&user) else {
+            continue;
+        };
+        if transfers.accept.is_empty() {
+            continue;
+        }
+        for acceptance in transfers.accept.drain(..) {
+            if acceptance.from == user || parse_username(&acceptance.from).is_err() {
+                eprintln!("'{}' : Invalid acceptance from '{}'", user, acceptance.from);
+                continue;
+            }
+            let offered = load_transfers(&acceptance.from).is_ok_and(|t| {
+                t.offer
+                    .iter()
+                    .any(|offer| offer.to == user && offer.path == acceptance.path)
+            });
+            if !offered {
+                continue;
+            }
+            let from = Path::new(&acceptance.from).join(&acceptance.path);
+            match complete(
+                &acceptance.from,
+                &acceptance.path,
+                &user,
+                &acceptance.destination,
+            ) {
+                Ok(()) => println!(
+                    "'{}' : Transferred to '{}'",
+                    from.display(),
+                    Path::new(&user).join(&acceptance.destination).display()
+                ),
+                Err(err) => eprintln!("'{}' : {}", from.display(), err),
+            }
+        }
+        if let Err(err) = save_transfers(&user, &transfers) {
+            eprintln!("'{}' : {}", user, err);
+        }
+    }
+}
+
+/// Transfer every repository of a user, placed in a folder named after them by default
+fn transfer_all(from: &str, to: &str, into: Option<&Path>) {
+    let repo_home = config().repo_path.join(from);
+    if from == to {
+        eprintln!("Cannot transfer repositories to the same user");
+        process::exit(1);
+    }
+    if !repo_home.is_dir() {
+        eprintln!("No repositories of '{}'", from);
+        process::exit(1);
+    }
+    let into = into.map(Path::to_path_buf).unwrap_or(PathBuf::from(from));
+    let paths = bare_repos(&repo_home)
+        .into_iter()
+        .filter(|path| !path.starts_with(&config().tmp_dir))
+        .collect::<Vec<_>>();
+
+    let mut transferred = 0;
+    for path in &paths {
+        let destination = into.join(path);
+        match complete(from, path, to, &destination) {
+            Ok(()) => {
+                println!(
+                    "'{}' : Transferred to '{}'",
+                    path.display(),
+                    destination.display()
+                );
+                transferred += 1;
+            }
+            Err(err) => eprintln!("'{}' : {}", path.display(), err),
+        }
+    }
+    println!(
+        "Transferred {}/{} repositories from '{}' to '{}'",
+        transferred,
+        paths.len(),
+        from,
+        to
+    );
+    if transferred < paths.len() {
+        process::exit(1);
+    }
+}
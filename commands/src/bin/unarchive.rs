@@ -0,0 +1,46 @@
+use clap::Parser;
+use commands::filter::{parse_repo_filter, FilterTerm, RepoFilter};
+use commands::orgs::{require_role, Role};
+use commands::{get_repo_home, is_archived, run_git, REPO_OPTION_SECTION};
+use std::process;
+
+/// Make archived repositories writable again
+#[derive(Parser)]
+#[command(about, arg_required_else_help = true)]
+struct Cli {
+    /// Paths to repositories, optionally narrowed by filters
+    #[arg(required = true, num_args = 1.., value_parser = clap::builder::ValueParser::new(parse_repo_filter))]
+    path: Vec<FilterTerm>,
+}
+
+fn main() {
+    let args = Cli::parse();
+
+    let paths = RepoFilter::new(&args.path).repos();
+    if paths.is_empty() {
+        eprintln!("No matching repositories found");
+        process::exit(1);
+    }
+
+    let repo_home = get_repo_home();
+    let key = format!("{}.archived", REPO_OPTION_SECTION);
+    let mut unarchived = 0;
+    for path in &paths {
+        let git_dir = repo_home.join(path);
+        if let Err(err) = require_role(path, Role::Owner) {
+            eprintln!("'{}' : {}", path.display(), err);
+            continue;
+        }
+        if !is_archived(&git_dir) {
+            continue;
+        }
+        run_git(&git_dir, &["config", "--unset", &key]).expect("Failed to unarchive repository");
+        println!("Unarchived '{}'", path.display());
+        unarchived += 1;
+    }
+    match unarchived {
+        0 => println!("No matching repositories are archived"),
+        1 => println!("Unarchived 1 repository"),
+        _ => println!("Unarchived {} repositories", unarchived),
+    }
+}
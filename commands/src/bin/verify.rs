@@ -0,0 +1,118 @@
+use clap::Parser;
+use commands::filter::{parse_repo_filter, FilterTerm, RepoFilter};
+use commands::{all_bare_repos, get_repo_home, git_command, is_server, set_repo_option, unix_time};
+use std::path::Path;
+use std::process;
+
+/// Check the integrity of repositories
+#[derive(Parser)]
+#[command(about, arg_required_else_help = true)]
+struct Cli {
+    /// Paths to repositories, optionally narrowed by filters
+    #[arg(required_unless_present = "scheduled", num_args = 1.., value_parser = clap::builder::ValueParser::new(parse_repo_filter))]
+    path: Vec<FilterTerm>,
+    /// Show every problem found rather than a summary
+    #[arg(short, long)]
+    verbose: bool,
+    /// Verify every repository on the server
+    #[arg(long, hide = true, conflicts_with = "path")]
+    scheduled: bool,
+}
+
+fn main() {
+    let args = Cli::parse();
+
+    let repos = if args.scheduled {
+        if !is_server() {
+            eprintln!("Scheduled verification can only be run by the server");
+            process::exit(1);
+        }
+        all_bare_repos()
+    } else {
+        let repo_home = get_repo_home();
+        RepoFilter::new(&args.path)
+            .repos()
+            .into_iter()
+            .map(|path| (path.clone(), repo_home.join(path)))
+            .collect()
+    };
+
+    if repos.is_empty() {
+        eprintln!("No matching repositories found");
+        process::exit(1);
+    }
+
+    let mut broken = 0;
+    for (path, git_dir) in &repos {
+        let problems = match verify(git_dir) {
+            Ok(problems) => problems,
+            Err(err) => {
+                eprintln!("'{}' : {}", path.display(), err);
+                broken += 1;
+                continue;
+            }
+        };
+
+        match problems.len() {
+            0 => println!("'{}' : ok", path.display()),
+            1 => eprintln!("'{}' : 1 problem", path.display()),
+            n => eprintln!("'{}' : {} problems", path.display(), n),
+        }
+        if !problems.is_empty() {
+            broken += 1;
+            let shown = if args.verbose { problems.len() } else { 5 };
+            for problem in problems.iter().take(shown) {
+                eprintln!("  {}", problem);
+            }
+            if problems.len() > shown {
+                eprintln!("  ... use --verbose to show all");
+            }
+        }
+    }
+
+    match (repos.len(), broken) {
+        (1, 0) => println!("Verified 1 repository"),
+        (n, 0) => println!("Verified {} repositories", n),
+        (1, _) => {
+            eprintln!("Verified 1 repository with problems");
+            process::exit(1);
+        }
+        (n, _) => {
+            eprintln!("Verified {} repositories, {} with problems", n, broken);
+            process::exit(1);
+        }
+    }
+}
+
+fn verify(git_dir: &Path) -> Result<Vec<String>, String> {
+    let output = git_command(git_dir)
+        .args([
+            "fsck",
+            "--full",
+            "--strict",
+            "--no-dangling",
+            "--no-progress",
+        ])
+        .output()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+
+    let problems = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .chain(String::from_utf8_lossy(&output.stderr).lines())
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("notice:"))
+        .map(String::from)
+        .collect::<Vec<_>>();
+    let problems = match (output.status.success(), problems.is_empty()) {
+        (false, true) => vec![String::from("fsck failed without reporting a problem")],
+        _ => problems,
+    };
+
+    let outcome = match problems.len() {
+        0 => String::from("ok"),
+        n => format!("{} problems", n),
+    };
+    set_repo_option(git_dir, "verified", &unix_time().to_string())?;
+    set_repo_option(git_dir, "verifyResult", &outcome)?;
+    Ok(problems)
+}
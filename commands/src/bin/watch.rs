@@ -0,0 +1,105 @@
+use clap::Parser;
+use commands::config::config;
+use commands::email::{load_watches, parse_email, save_watches, send_queued, Watch};
+use commands::{is_server, parse_repo_glob};
+use std::process;
+
+/// Get emails about pushes to repositories, or list watched repositories
+#[derive(Parser)]
+#[command(about)]
+struct Cli {
+    /// Paths or globs of repositories to watch
+    #[arg(value_parser = clap::builder::ValueParser::new(parse_glob))]
+    glob: Vec<String>,
+    /// Address to email, the one configured for you if omitted
+    #[arg(short, long, value_parser = clap::builder::ValueParser::new(parse_email))]
+    email: Option<String>,
+    /// Stop watching instead
+    #[arg(short, long)]
+    remove: bool,
+    /// Send the emails queued by every user
+    #[arg(long, hide = true)]
+    scheduled: bool,
+}
+
+fn parse_glob(glob: &str) -> Result<String, String> {
+    parse_repo_glob(glob).map(|_| glob.to_string())
+}
+
+fn main() {
+    let args = Cli::parse();
+
+    if args.scheduled {
+        if !is_server() {
+            eprintln!("Scheduled emails can only be sent by the server");
+            process::exit(1);
+        }
+        send_queued();
+        return;
+    }
+
+    let mut watches = load_watches().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+
+    if args.glob.is_empty() {
+        if watches.is_empty() {
+            println!("Not watching any repositories");
+            return;
+        }
+        for watch in &watches {
+            println!("'{}' -> {}", watch.glob, watch.email);
+        }
+        if config().smtp.host.is_empty() {
+            println!("Email notifications are not configured on this server");
+        }
+        return;
+    }
+
+    if args.remove {
+        let before = watches.len();
+        watches.retain(|watch| {
+            let email_matches = args.email.as_ref().is_none_or(|e| *e == watch.email);
+            !(args.glob.contains(&watch.glob) && email_matches)
+        });
+        save(&watches);
+        match before - watches.len() {
+            1 => println!("Removed 1 watch"),
+            n => println!("Removed {} watches", n),
+        }
+        return;
+    }
+
+    let Some(email) = args.email.or_else(|| config().email.clone()) else {
+        eprintln!("No email address is configured for you, use --email");
+        process::exit(1);
+    };
+    let mut added = Vec::new();
+    for glob in args.glob {
+        let watch = Watch {
+            glob,
+            email: email.clone(),
+        };
+        if watches.contains(&watch) {
+            println!("Already watching '{}'", watch.glob);
+            continue;
+        }
+        added.push(format!(
+            "Watching '{}', emailing {}",
+            watch.glob, watch.email
+        ));
+        watches.push(watch);
+    }
+    save(&watches);
+    for message in added {
+        println!("{}", message);
+    }
+}
+
+fn save(watches: &[Watch]) {
+    save_watches(watches).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+}
@@ -0,0 +1,177 @@
+use clap::{Parser, Subcommand};
+use commands::stats::format_age;
+use commands::webhooks::{
+    deliver, deliver_queued, load_webhooks, parse_url, read_delivery_log, save_webhooks, Webhook,
+};
+use commands::{get_username, is_server, parse_repo_glob, random_hex, unix_time};
+use serde_json::json;
+use std::process;
+
+/// Notify other services of pushes and repository changes
+#[derive(Parser)]
+#[command(about, arg_required_else_help = true)]
+struct Cli {
+    #[command(subcommand)]
+    action: Option<Action>,
+    /// Deliver the events queued by every user
+    #[arg(long, hide = true)]
+    scheduled: bool,
+}
+
+#[derive(Subcommand)]
+enum Action {
+    /// List registered webhooks
+    List,
+    /// Register a webhook for repositories matching a glob
+    Add {
+        /// URL to POST payloads to
+        #[arg(value_parser = clap::builder::ValueParser::new(parse_url))]
+        url: String,
+        /// Repositories to notify about, all of them if omitted
+        #[arg(default_value = ".", value_parser = clap::builder::ValueParser::new(parse_glob))]
+        glob: String,
+        /// Secret used to sign payloads, generated if omitted
+        #[arg(short, long, value_parser = clap::builder::ValueParser::new(parse_secret))]
+        secret: Option<String>,
+    },
+    /// Remove a webhook
+    Remove {
+        /// Number of the webhook, as shown by list
+        id: usize,
+    },
+    /// Send a ping to a webhook
+    Test {
+        /// Number of the webhook, as shown by list
+        id: usize,
+    },
+    /// Show recent delivery attempts
+    Log {
+        /// Number of attempts to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        count: usize,
+    },
+}
+
+fn parse_glob(glob: &str) -> Result<String, String> {
+    parse_repo_glob(glob).map(|_| glob.to_string())
+}
+
+fn parse_secret(secret: &str) -> Result<String, String> {
+    match secret.len() {
+        0..=15 => Err(String::from("Secret must be at least 16 characters")),
+        257.. => Err(String::from("Secret cannot exceed 256 characters")),
+        _ => Ok(secret.to_string()),
+    }
+}
+
+fn main() {
+    let args = Cli::parse();
+
+    if args.scheduled {
+        if !is_server() {
+            eprintln!("Scheduled deliveries can only be run by the server");
+            process::exit(1);
+        }
+        deliver_queued();
+        return;
+    }
+    let Some(action) = args.action else {
+        return;
+    };
+
+    let mut webhooks = load_webhooks().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+    let find = |id: usize| -> Webhook {
+        match id.checked_sub(1).and_then(|i| webhooks.get(i)) {
+            Some(webhook) => webhook.clone(),
+            None => {
+                eprintln!("No webhook {}", id);
+                process::exit(1);
+            }
+        }
+    };
+
+    match action {
+        Action::List => {
+            if webhooks.is_empty() {
+                println!("No webhooks registered");
+                return;
+            }
+            println!("{:<3} {:<24} URL", "ID", "REPOSITORIES");
+            for (i, webhook) in webhooks.iter().enumerate() {
+                println!("{:<3} {:<24} {}", i + 1, webhook.glob, webhook.url);
+            }
+        }
+        Action::Add { url, glob, secret } => {
+            let generated = secret.is_none();
+            let secret = secret.unwrap_or_else(|| random_hex(32));
+            webhooks.push(Webhook {
+                url: url.clone(),
+                glob,
+                secret: secret.clone(),
+            });
+            save_webhooks(&webhooks).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                process::exit(1);
+            });
+            println!("Added webhook {} for '{}'", webhooks.len(), url);
+            if generated {
+                println!("Payloads are signed with the secret '{}'", secret);
+            }
+        }
+        Action::Remove { id } => {
+            let webhook = find(id);
+            webhooks.remove(id - 1);
+            save_webhooks(&webhooks).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                process::exit(1);
+            });
+            println!("Removed webhook for '{}'", webhook.url);
+        }
+        Action::Test { id } => {
+            let webhook = find(id);
+            let payload = json!({
+                "event": "ping",
+                "user": get_username(),
+                "repository": webhook.glob,
+                "timestamp": unix_time(),
+            });
+            match deliver(&get_username(), &webhook, "ping", &payload) {
+                Ok(status) => println!("'{}' : Delivered, HTTP {}", webhook.url, status),
+                Err(err) => {
+                    eprintln!("'{}' : Failed, {}", webhook.url, err);
+                    process::exit(1);
+                }
+            }
+        }
+        Action::Log { count } => {
+            let deliveries = read_delivery_log();
+            if deliveries.is_empty() {
+                println!("No deliveries yet");
+                return;
+            }
+            println!(
+                "{:<16} {:<16} {:<7} {:<8} {:<24} {:<24} URL",
+                "WHEN", "DELIVERY", "EVENT", "ATTEMPT", "RESULT", "REPOSITORY"
+            );
+            for delivery in deliveries.iter().rev().take(count) {
+                let result = match delivery.succeeded() {
+                    true => format!("ok ({})", delivery.outcome),
+                    false => delivery.outcome.clone(),
+                };
+                println!(
+                    "{:<16} {:<16} {:<7} {:<8} {:<24} {:<24} {}",
+                    format_age(delivery.time),
+                    delivery.id,
+                    delivery.event,
+                    delivery.attempt,
+                    result,
+                    delivery.repository,
+                    delivery.url
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,409 @@
+//! Jobs run for pushed commits containing a `.ci.toml`
+//!
+//! Pushes queue a job for every updated branch, which `ci --scheduled` later runs in a
+//! temporary checkout. Each step runs as the unprivileged account set under `[ci] user`, in
+//! its own mount and process namespaces which hide repositories, homes, the server config
+//! and other jobs, and anything it leaves running is killed when it ends. The state and
+//! output of each job are kept in `ci/<commit>/` inside the bare repository.
+
+use crate::config::{config, config_path, Ci};
+use crate::hooks::RefUpdate;
+use crate::stats::parse_duration;
+use crate::status::{set_status, StatusState};
+use crate::{
+    git_command, is_server, random_hex, run_git, unix_time, BASE_USER_PATH, GATEWAY_ACCOUNT,
+};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const CI_FILE: &str = ".ci.toml";
+/// Context of the commit statuses reported for jobs
+pub const STATUS_CONTEXT: &str = "ci";
+const JOB_FOLDER: &str = "ci";
+const JOB_FILE: &str = "job.toml";
+const LOG_FILE: &str = "log";
+/// Search path of steps, whose environment is otherwise cleared
+const SANDBOX_PATH: &str = "/usr/local/bin:/usr/bin:/bin";
+/// Where the checkout is mounted inside the sandbox
+const SANDBOX_WORK_DIR: &str = "/srv/work";
+/// Run inside fresh namespaces as root, hiding everything a step must not see before
+/// dropping to the CI account, with the arguments work folder, config, uid, gid, step and
+/// the folders to hide
+const SANDBOX_SCRIPT: &str = r#"set -e
+work=$1 config=$2 uid=$3 gid=$4 step=$5
+shift 5
+if [ -f "$config" ]; then mount --bind /dev/null "$config"; fi
+mount -t tmpfs -o mode=755 tmpfs /srv
+mkdir /srv/work
+mount --bind "$work" /srv/work
+for dir in "$@" /root /home; do
+  if [ -d "$dir" ]; then mount -t tmpfs -o mode=755 tmpfs "$dir"; fi
+done
+for dir in /tmp /var/tmp /dev/shm; do
+  if [ -d "$dir" ]; then mount -t tmpfs -o mode=1777 tmpfs "$dir"; fi
+done
+cd /srv/work
+exec setpriv --reuid="$uid" --regid="$gid" --clear-groups --no-new-privs sh -c "$step"
+"#;
+
+/// Steps of a job, as read from `.ci.toml`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pipeline {
+    /// Longest the job may run, capped by the server's limit
+    pub timeout: Option<String>,
+    #[serde(rename = "step")]
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    pub name: String,
+    /// Shell command to run from the root of the checkout
+    pub run: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Running,
+    Passed,
+    Failed,
+    /// The job could not be run at all, such as due to an invalid `.ci.toml`
+    Error,
+}
+
+impl JobState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobState::Queued => "queued",
+            JobState::Running => "running",
+            JobState::Passed => "passed",
+            JobState::Failed => "failed",
+            JobState::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Job {
+    pub commit: String,
+    pub branch: String,
+    pub state: JobState,
+    pub queued: u64,
+    pub started: Option<u64>,
+    pub finished: Option<u64>,
+}
+
+impl Job {
+    pub fn duration(&self) -> Option<u64> {
+        Some(self.finished?.saturating_sub(self.started?))
+    }
+}
+
+fn job_dir(git_dir: &Path, commit: &str) -> PathBuf {
+    git_dir.join(JOB_FOLDER).join(commit)
+}
+
+pub fn log_path(git_dir: &Path, commit: &str) -> PathBuf {
+    job_dir(git_dir, commit).join(LOG_FILE)
+}
+
+pub fn load_job(git_dir: &Path, commit: &str) -> Option<Job> {
+    let raw = fs::read_to_string(job_dir(git_dir, commit).join(JOB_FILE)).ok()?;
+    toml::from_str(&raw).ok()
+}
+
+fn save_job(git_dir: &Path, job: &Job) -> Result<(), String> {
+    let dir = job_dir(git_dir, &job.commit);
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let raw = toml::to_string(job).map_err(|e| e.to_string())?;
+    fs::write(dir.join(JOB_FILE), raw).map_err(|e| format!("Failed to save job: {}", e))?;
+    for path in [dir.parent().unwrap(), &dir, &dir.join(JOB_FILE)] {
+        hand_over(git_dir, path);
+    }
+    Ok(())
+}
+
+/// Give a file the server created in a repository to the repository's owner
+fn hand_over(git_dir: &Path, path: &Path) {
+    if !is_server() {
+        return;
+    }
+    if let Ok(owner) = fs::metadata(git_dir) {
+        unix::fs::lchown(path, Some(owner.uid()), Some(owner.gid())).ok();
+    }
+}
+
+/// Every job of a repository, most recently queued first
+pub fn jobs(git_dir: &Path) -> Vec<Job> {
+    let Ok(entries) = fs::read_dir(git_dir.join(JOB_FOLDER)) else {
+        return Vec::new();
+    };
+    let mut jobs = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| load_job(git_dir, entry.file_name().to_str()?))
+        .collect::<Vec<_>>();
+    jobs.sort_by_key(|job| u64::MAX - job.queued);
+    jobs
+}
+
+/// Queue a job for every pushed branch whose new commit has a `.ci.toml`
+pub fn queue_jobs(git_dir: &Path, updates: &[RefUpdate]) {
+    if !config().ci.enabled {
+        return;
+    }
+    for update in updates.iter().filter(|update| !update.is_delete()) {
+        let Some(branch) = update.branch() else {
+            continue;
+        };
+        let ci_file = format!("{}:{}", update.new, CI_FILE);
+        if run_git(git_dir, &["cat-file", "-e", &ci_file]).is_err() {
+            continue;
+        }
+        if load_job(git_dir, &update.new).is_some() {
+            continue;
+        }
+
+        let job = Job {
+            commit: update.new.clone(),
+            branch: branch.to_string(),
+            state: JobState::Queued,
+            queued: unix_time(),
+            started: None,
+            finished: None,
+        };
+        match save_job(git_dir, &job) {
+            Ok(()) => eprintln!("Queued CI job for '{}' at {:.7}", branch, job.commit),
+            Err(err) => {
+                eprintln!("Failed to queue CI job for '{}' : {}", branch, err);
+                continue;
+            }
+        }
+        report_status(git_dir, &job);
+    }
+}
+
+/// Claim a queued job so that no other runner picks it up
+pub fn claim_job(git_dir: &Path, commit: &str) -> bool {
+    let lock = job_dir(git_dir, commit).join("lock");
+    let claimed = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&lock)
+        .is_ok();
+    hand_over(git_dir, &lock);
+    claimed
+}
+
+/// Run a queued job with the CI settings of the repository's owner, recording its state and
+/// output as it goes
+pub fn run_job(
+    git_dir: &Path,
+    repository: &Path,
+    mut job: Job,
+    settings: &Ci,
+) -> Result<JobState, String> {
+    let mut log = File::create(log_path(git_dir, &job.commit))
+        .map_err(|e| format!("Failed to create log: {}", e))?;
+    hand_over(git_dir, &log_path(git_dir, &job.commit));
+    job.state = JobState::Running;
+    job.started = Some(unix_time());
+    save_job(git_dir, &job)?;
+
+    let work_dir = std::env::temp_dir().join(format!("ci-{}", random_hex(8)));
+    let state = match run_pipeline(git_dir, repository, &job, settings, &work_dir, &mut log) {
+        Ok(state) => state,
+        Err(err) => {
+            writeln!(log, "{}", err).ok();
+            JobState::Error
+        }
+    };
+    fs::remove_dir_all(&work_dir).ok();
+
+    job.state = state;
+    job.finished = Some(unix_time());
+    save_job(git_dir, &job)?;
+    report_status(git_dir, &job);
+    Ok(state)
+}
+
+/// Record the state of a job as the commit's `ci` status
+fn report_status(git_dir: &Path, job: &Job) {
+    let state = match job.state {
+        JobState::Queued | JobState::Running => StatusState::Pending,
+        JobState::Passed => StatusState::Success,
+        JobState::Failed | JobState::Error => StatusState::Failure,
+    };
+    if let Err(err) = set_status(git_dir, &job.commit, STATUS_CONTEXT, state, None) {
+        eprintln!("Failed to record CI status for {:.7} : {}", job.commit, err);
+    }
+}
+
+/// Account steps run as, which must be unprivileged and own none of the repositories
+fn sandbox_account(git_dir: &Path, settings: &Ci) -> Result<users::User, String> {
+    if !is_server() {
+        return Err(String::from("Jobs can only be run by the server"));
+    }
+    let account = users::get_user_by_name(&settings.user)
+        .ok_or_else(|| format!("No CI account '{}'", settings.user))?;
+    let owner = fs::metadata(git_dir).map_err(|e| e.to_string())?.uid();
+    if account.uid() == 0 || account.uid() == owner || settings.user == GATEWAY_ACCOUNT {
+        return Err(format!(
+            "CI account '{}' must be unprivileged and own no repositories",
+            settings.user
+        ));
+    }
+    Ok(account)
+}
+
+fn run_pipeline(
+    git_dir: &Path,
+    repository: &Path,
+    job: &Job,
+    settings: &Ci,
+    work_dir: &Path,
+    log: &mut File,
+) -> Result<JobState, String> {
+    let ci_file = format!("{}:{}", job.commit, CI_FILE);
+    let raw = run_git(git_dir, &["cat-file", "blob", &ci_file])?;
+    let pipeline =
+        toml::from_str::<Pipeline>(&raw).map_err(|e| format!("Invalid {}: {}", CI_FILE, e))?;
+
+    let account = sandbox_account(git_dir, settings)?;
+    let mut timeout = parse_duration(&settings.timeout)?;
+    if let Some(limit) = &pipeline.timeout {
+        timeout = timeout.min(parse_duration(limit)?);
+    }
+    let deadline = Instant::now() + Duration::from_secs(timeout);
+
+    fs::DirBuilder::new()
+        .mode(0o700)
+        .create(work_dir)
+        .map_err(|e| format!("Failed to create work folder: {}", e))?;
+    unix::fs::chown(
+        work_dir,
+        Some(account.uid()),
+        Some(account.primary_group_id()),
+    )
+    .map_err(|e| format!("Failed to hand over work folder: {}", e))?;
+    checkout(git_dir, &job.commit, work_dir, &account)?;
+
+    for step in &pipeline.steps {
+        writeln!(log, "==> {}\n$ {}", step.name, step.run).map_err(|e| e.to_string())?;
+        let output = log.try_clone().map_err(|e| e.to_string())?;
+        let errors = log.try_clone().map_err(|e| e.to_string())?;
+        let mut child = sandbox_command(&account, work_dir, &step.run)
+            .env_clear()
+            .env("PATH", SANDBOX_PATH)
+            .env("HOME", SANDBOX_WORK_DIR)
+            .env("CI", "true")
+            .env("CI_COMMIT", &job.commit)
+            .env("CI_BRANCH", &job.branch)
+            .env("CI_REPOSITORY", repository)
+            .stdin(Stdio::null())
+            .stdout(output)
+            .stderr(errors)
+            .process_group(0)
+            .spawn()
+            .map_err(|e| format!("Failed to run '{}': {}", step.name, e))?;
+
+        let status = loop {
+            if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+                break Some(status);
+            }
+            if Instant::now() >= deadline {
+                break None;
+            }
+            thread::sleep(Duration::from_millis(200));
+        };
+        // Stop everything the step started, not just its shell
+        Command::new("kill")
+            .args(["-s", "KILL", "--", &format!("-{}", child.id())])
+            .stderr(Stdio::null())
+            .status()
+            .ok();
+        child.wait().ok();
+        match status {
+            Some(status) if status.success() => (),
+            Some(status) => {
+                writeln!(log, "==> '{}' failed with {}", step.name, status).ok();
+                return Ok(JobState::Failed);
+            }
+            None => {
+                writeln!(log, "==> Timed out after {} seconds", timeout).ok();
+                return Ok(JobState::Failed);
+            }
+        }
+    }
+    writeln!(log, "==> Passed").ok();
+    Ok(JobState::Passed)
+}
+
+/// Command running a step in a sandbox, whose processes all end when the step's shell does
+fn sandbox_command(account: &users::User, work_dir: &Path, run: &str) -> Command {
+    let mut command = Command::new("unshare");
+    command
+        .args([
+            "--mount",
+            "--pid",
+            "--ipc",
+            "--uts",
+            "--fork",
+            "--kill-child",
+        ])
+        .args(["--mount-proc", "--", "sh", "-c", SANDBOX_SCRIPT, "sandbox"])
+        .arg(work_dir)
+        .arg(config_path())
+        .arg(account.uid().to_string())
+        .arg(account.primary_group_id().to_string())
+        .arg(run)
+        .arg(&config().repo_path)
+        .arg(BASE_USER_PATH)
+        .current_dir("/");
+    command
+}
+
+/// Extract the files of a commit into a folder, owned by the account running the steps
+fn checkout(
+    git_dir: &Path,
+    commit: &str,
+    work_dir: &Path,
+    account: &users::User,
+) -> Result<(), String> {
+    let mut archive = git_command(git_dir)
+        .args(["archive", "--format=tar", commit])
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+    let extracted = Command::new("tar")
+        .arg("-x")
+        .arg("-C")
+        .arg(work_dir)
+        .stdin(archive.stdout.take().unwrap())
+        .uid(account.uid())
+        .gid(account.primary_group_id())
+        .status();
+    let archived = archive.wait();
+    match (archived, extracted) {
+        (Ok(a), Ok(e)) if a.success() && e.success() => Ok(()),
+        _ => Err(String::from("Failed to check out commit")),
+    }
+}
+
+/// Print the output of a job
+pub fn print_log(git_dir: &Path, commit: &str) -> Result<(), String> {
+    let mut log = File::open(log_path(git_dir, commit)).map_err(|_| "No output yet")?;
+    io::copy(&mut log, &mut io::stdout()).map_err(|e| e.to_string())?;
+    Ok(())
+}
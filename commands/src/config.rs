@@ -0,0 +1,365 @@
+use crate::orgs::repo_org;
+use crate::policies::Policy;
+use crate::stats::{parse_duration, parse_size};
+use crate::status::parse_context;
+use crate::{get_username, parse_repo_glob};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::{env, fs, io, process};
+use toml::{Table, Value};
+
+pub const CONFIG_PATH: &str = "/etc/git-server.toml";
+/// Environment variable overriding the location of the config file
+pub const CONFIG_PATH_VAR: &str = "GIT_SERVER_CONFIG";
+
+const DEFAULT_NAME_PATTERN: &str = "[A-Za-z0-9_\\-]+";
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Server configuration, with any overrides for the current user applied
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Folder holding the bare repositories of every user
+    pub repo_path: PathBuf,
+    /// Folder used to stage repositories while they are being moved
+    pub tmp_dir: String,
+    /// Address notifications for the user are sent to by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    pub limits: Limits,
+    pub protection: Protection,
+    pub smtp: Smtp,
+    pub webhooks: Webhooks,
+    pub ci: Ci,
+    /// Policies pushes to matching repositories must follow, which users cannot remove
+    #[serde(rename = "policy", skip_serializing_if = "Vec::is_empty")]
+    pub policies: Vec<Policy>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    pub max_path_length: usize,
+    pub max_glob_length: usize,
+    pub max_depth: usize,
+    /// Pattern each repository and folder name must match
+    pub name_pattern: String,
+    /// Largest file a push may add, such as `100M`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_blob_size: Option<String>,
+    /// Largest amount of new data a single push may add
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_push_size: Option<String>,
+    /// Most Git LFS data a repository may store
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_lfs_size: Option<String>,
+    /// Size limits for repositories matching a glob, later entries taking precedence
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub repos: Vec<RepoLimits>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RepoLimits {
+    /// Repositories the limits apply to, as accepted by `parse_repo_glob`
+    pub glob: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_blob_size: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_push_size: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_lfs_size: Option<String>,
+}
+
+/// Size limits in bytes in effect for a repository
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SizeLimits {
+    pub max_blob: Option<u64>,
+    pub max_push: Option<u64>,
+    pub max_lfs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Protection {
+    /// Globs of branches which cannot be deleted, renamed or force pushed
+    pub branches: Vec<String>,
+    /// Contexts which must report success for a commit before protected branches move to it
+    pub required_statuses: Vec<String>,
+}
+
+/// Delivery of webhooks, which are sent by the server
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Webhooks {
+    /// Whether webhooks may reach loopback, private and link-local addresses
+    pub allow_private: bool,
+}
+
+/// Jobs run for pushed commits containing a `.ci.toml`
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Ci {
+    /// Jobs run code on the server, so they must be enabled explicitly
+    pub enabled: bool,
+    /// Longest a job may run, such as `30m`
+    pub timeout: String,
+    /// Unprivileged account steps run as, which must not own any repositories
+    pub user: String,
+}
+
+/// Relay used to send email notifications
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Smtp {
+    /// Email notifications are disabled unless a host is set
+    pub host: String,
+    pub port: u16,
+    pub security: SmtpSecurity,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing)]
+    pub password: Option<String>,
+    pub from: String,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    None,
+    StartTls,
+    Tls,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            repo_path: PathBuf::from("/srv/repos"),
+            tmp_dir: String::from(".tmp"),
+            email: None,
+            limits: Limits::default(),
+            protection: Protection::default(),
+            smtp: Smtp::default(),
+            webhooks: Webhooks::default(),
+            ci: Ci::default(),
+            policies: Vec::new(),
+        }
+    }
+}
+
+impl Default for Ci {
+    fn default() -> Self {
+        Ci {
+            enabled: false,
+            timeout: String::from("30m"),
+            user: String::from("nobody"),
+        }
+    }
+}
+
+impl Default for Smtp {
+    fn default() -> Self {
+        Smtp {
+            host: String::new(),
+            port: 25,
+            security: SmtpSecurity::None,
+            username: None,
+            password: None,
+            from: String::from("git-server@localhost"),
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_path_length: 256,
+            max_glob_length: 64,
+            max_depth: 4,
+            name_pattern: String::from(DEFAULT_NAME_PATTERN),
+            max_blob_size: None,
+            max_push_size: None,
+            max_lfs_size: None,
+            repos: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Load the config file for a user, falling back to defaults if it does not exist
+    pub fn load(username: &str) -> Result<Config, String> {
+        Config::load_overriding(Some(username))
+    }
+
+    /// Load the config file without the overrides of any user
+    pub fn load_global() -> Result<Config, String> {
+        Config::load_overriding(None)
+    }
+
+    fn load_overriding(username: Option<&str>) -> Result<Config, String> {
+        let path = config_path();
+        let raw = match fs::read_to_string(&path) {
+            Ok(raw) => raw,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(format!("Failed to read '{}': {}", path.display(), err)),
+        };
+        let mut table = raw
+            .parse::<Table>()
+            .map_err(|e| format!("Failed to parse '{}': {}", path.display(), e))?;
+
+        let overrides = match table.remove("users") {
+            None => None,
+            Some(Value::Table(mut users)) => match username.and_then(|name| users.remove(name)) {
+                None => None,
+                Some(Value::Table(overrides)) => Some(overrides),
+                Some(_) => {
+                    return Err(format!(
+                        "Overrides for '{}' must be a table",
+                        username.unwrap_or_default()
+                    ))
+                }
+            },
+            Some(_) => return Err(String::from("'users' must be a table")),
+        };
+        if let Some(overrides) = overrides {
+            merge_tables(&mut table, overrides);
+        }
+
+        let config = Config::deserialize(table)
+            .map_err(|e| format!("Invalid config in '{}': {}", path.display(), e))?;
+        Regex::new(&config.limits.name_pattern)
+            .map_err(|e| format!("Invalid name pattern: {}", e))?;
+        config.limits.validate()?;
+        config.protection.branch_set()?;
+        for context in &config.protection.required_statuses {
+            parse_context(context).map_err(|e| format!("Invalid required status: {}", e))?;
+        }
+        parse_duration(&config.ci.timeout).map_err(|e| format!("Invalid CI timeout: {}", e))?;
+        for policy in &config.policies {
+            policy.validate()?;
+        }
+        Ok(config)
+    }
+
+    pub fn path_regex(&self) -> Regex {
+        let name = &self.limits.name_pattern;
+        Regex::new(&format!("^(?:(?:{0})/)*(?:{0})(?:\\.git|/)?$", name)).unwrap()
+    }
+
+    pub fn has_default_name_pattern(&self) -> bool {
+        self.limits.name_pattern == DEFAULT_NAME_PATTERN
+    }
+}
+
+impl Limits {
+    fn validate(&self) -> Result<(), String> {
+        let sizes = self
+            .repos
+            .iter()
+            .flat_map(|repo| [&repo.max_blob_size, &repo.max_push_size, &repo.max_lfs_size])
+            .chain([&self.max_blob_size, &self.max_push_size, &self.max_lfs_size]);
+        for size in sizes.flatten() {
+            parse_size(size).map_err(|e| format!("Invalid size limit: {}", e))?;
+        }
+        for repo in &self.repos {
+            Glob::new(&repo.glob)
+                .map_err(|e| format!("Invalid limits glob '{}': {}", repo.glob, e))?;
+        }
+        Ok(())
+    }
+
+    /// Size limits for a repository, with those of every matching glob applied in order
+    pub fn size_limits(&self, path: &Path) -> SizeLimits {
+        let mut blob = self.max_blob_size.as_deref();
+        let mut push = self.max_push_size.as_deref();
+        let mut lfs = self.max_lfs_size.as_deref();
+        for repo in self.repos.iter().filter(|repo| repo.matches(path)) {
+            blob = repo.max_blob_size.as_deref().or(blob);
+            push = repo.max_push_size.as_deref().or(push);
+            lfs = repo.max_lfs_size.as_deref().or(lfs);
+        }
+        let parse = |size: Option<&str>| size.and_then(|size| parse_size(size).ok());
+        SizeLimits {
+            max_blob: parse(blob),
+            max_push: parse(push),
+            max_lfs: parse(lfs),
+        }
+    }
+}
+
+impl RepoLimits {
+    fn matches(&self, path: &Path) -> bool {
+        match parse_repo_glob(&self.glob) {
+            Ok(glob) => {
+                let matcher = glob.compile_matcher();
+                path.ancestors().any(|p| matcher.is_match(p))
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+impl Protection {
+    fn branch_set(&self) -> Result<GlobSet, String> {
+        let mut builder = GlobSetBuilder::new();
+        for branch in &self.branches {
+            let glob = Glob::new(branch)
+                .map_err(|e| format!("Invalid protected branch '{}': {}", branch, e))?;
+            builder.add(glob);
+        }
+        builder.build().map_err(|e| e.to_string())
+    }
+
+    pub fn is_protected(&self, branch: &str) -> bool {
+        self.branch_set()
+            .is_ok_and(|branches| branches.is_match(branch))
+    }
+}
+
+/// Configuration in effect for the current user
+pub fn config() -> &'static Config {
+    CONFIG.get_or_init(|| match Config::load(&get_username()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1)
+        }
+    })
+}
+
+/// Size limits of a repository, where organisation repositories are shared by every member
+/// so the overrides of the current user do not apply to them
+pub fn size_limits(path: &Path) -> SizeLimits {
+    if repo_org(path).is_none() {
+        return config().limits.size_limits(path);
+    }
+    match Config::load_global() {
+        Ok(global) => global.limits.size_limits(path),
+        Err(err) => {
+            eprintln!("{}", err);
+            config().limits.size_limits(path)
+        }
+    }
+}
+
+pub fn config_path() -> PathBuf {
+    match env::var(CONFIG_PATH_VAR) {
+        Ok(path) => PathBuf::from(path),
+        Err(_) => PathBuf::from(CONFIG_PATH),
+    }
+}
+
+fn merge_tables(base: &mut Table, overrides: Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(value)) => merge_tables(base, value),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
@@ -0,0 +1,335 @@
+//! Email notifications of pushes to watched repositories
+//!
+//! Each user's subscriptions are kept in `.watchers.toml` inside their repository folder,
+//! and notifications are sent through the relay configured in the `[smtp]` section. A push
+//! notifies every user watching the repository, as long as it is theirs or their organisation's.
+//! Summaries are queued in `.email.queue/` by the pusher and sent by `watch --scheduled`, so
+//! a slow relay never holds up a push.
+
+use crate::config::{config, SmtpSecurity};
+use crate::hooks::RefUpdate;
+use crate::orgs::{repo_org, role, Role};
+use crate::transfers::all_users;
+use crate::{get_repo_home, get_username, parse_repo_glob, random_hex, run_git, unix_time};
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Address, Message, SmtpTransport, Transport};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{fs, io};
+
+pub const WATCHERS_FILE: &str = ".watchers.toml";
+/// Folder in a user's repository folder holding summaries of their pushes, until sent
+pub const QUEUE_FOLDER: &str = ".email.queue";
+
+/// Commits listed per ref before the rest are summarised
+const MAX_COMMITS: usize = 50;
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Watch {
+    /// Repositories to notify about, as accepted by `parse_repo_glob`
+    pub glob: String,
+    pub email: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+struct WatchersFile {
+    watch: Vec<Watch>,
+}
+
+/// A push summary waiting to be emailed to everyone watching the repository
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct QueuedEmail {
+    git_dir: PathBuf,
+    subject: String,
+    body: String,
+}
+
+impl Watch {
+    pub fn matches(&self, path: &Path) -> bool {
+        match parse_repo_glob(&self.glob) {
+            Ok(glob) => {
+                let matcher = glob.compile_matcher();
+                path.ancestors().any(|p| matcher.is_match(p))
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+fn watchers_path(user: &str) -> PathBuf {
+    config().repo_path.join(user).join(WATCHERS_FILE)
+}
+
+pub fn parse_email(email: &str) -> Result<String, String> {
+    email
+        .parse::<Address>()
+        .map(|address| address.to_string())
+        .map_err(|e| format!("Invalid email address: {}", e))
+}
+
+pub fn load_watches() -> Result<Vec<Watch>, String> {
+    load_user_watches(&get_username())
+}
+
+fn load_user_watches(user: &str) -> Result<Vec<Watch>, String> {
+    let path = watchers_path(user);
+    let raw = match fs::read_to_string(&path) {
+        Ok(raw) => raw,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(format!("Failed to read watchers: {}", err)),
+    };
+    toml::from_str::<WatchersFile>(&raw)
+        .map(|file| file.watch)
+        .map_err(|e| format!("Invalid watchers in '{}': {}", path.display(), e))
+}
+
+pub fn save_watches(watches: &[Watch]) -> Result<(), String> {
+    let file = WatchersFile {
+        watch: watches.to_vec(),
+    };
+    let raw = toml::to_string(&file).map_err(|e| e.to_string())?;
+    let path = watchers_path(&get_username());
+    fs::create_dir_all(path.parent().unwrap()).map_err(|e| e.to_string())?;
+    fs::write(path, raw).map_err(|e| format!("Failed to save watchers: {}", e))
+}
+
+/// Path of a bare repository as a user refers to it, if they have access to it
+fn path_for_user(git_dir: &Path, user: &str) -> Option<PathBuf> {
+    let repo_root = config().repo_path.canonicalize().ok()?;
+    let path = git_dir.canonicalize().ok()?;
+    let path = path.strip_prefix(repo_root).ok()?;
+    if let Ok(own) = path.strip_prefix(user) {
+        return Some(own.to_path_buf());
+    }
+    let org = repo_org(path)?;
+    role(&org, user).map(|_| path.to_path_buf())
+}
+
+/// Addresses of every user watching a repository
+fn watchers(git_dir: &Path) -> BTreeSet<String> {
+    let mut recipients = BTreeSet::new();
+    for user in all_users() {
+        let Some(path) = path_for_user(git_dir, &user) else {
+            continue;
+        };
+        match load_user_watches(&user) {
+            Ok(watches) => recipients.extend(
+                watches
+                    .into_iter()
+                    .filter(|watch| watch.matches(&path))
+                    .map(|watch| watch.email),
+            ),
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+    recipients
+}
+
+/// Queue a summary of a push for everyone watching the repository, reporting failures to
+/// queue it
+pub fn notify_push(git_dir: &Path, path: &Path, updates: &[RefUpdate]) {
+    if config().smtp.host.is_empty() {
+        return;
+    }
+    let Ok(git_dir) = git_dir.canonicalize() else {
+        return;
+    };
+    if watchers(&git_dir).is_empty() {
+        return;
+    }
+
+    let (subject, body) = push_summary(&git_dir, path, updates);
+    let queued = QueuedEmail {
+        git_dir,
+        subject,
+        body,
+    };
+    let dir = get_repo_home().join(QUEUE_FOLDER);
+    let file = dir.join(format!("{}-{}.json", unix_time(), random_hex(4)));
+    let result = fs::create_dir_all(&dir)
+        .and_then(|_| fs::write(file, serde_json::to_string(&queued).unwrap()));
+    if let Err(err) = result {
+        eprintln!("Failed to queue emails: {}", err);
+    }
+}
+
+/// Whether a user could have pushed to a repository, so their summaries of it are trusted
+fn can_push(git_dir: &Path, user: &str) -> bool {
+    let Some(path) = path_for_user(git_dir, user) else {
+        return false;
+    };
+    match repo_org(&path) {
+        Some(org) => role(&org, user).is_some_and(|role| role >= Role::Write),
+        None => true,
+    }
+}
+
+/// Send the summaries every user has queued, run by the server as it reads everyone's watchers
+pub fn send_queued() {
+    if config().smtp.host.is_empty() {
+        return;
+    }
+    for user in all_users() {
+        let dir = config().repo_path.join(&user).join(QUEUE_FOLDER);
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut files = entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .collect::<Vec<_>>();
+        files.sort();
+
+        for file in files {
+            let raw = fs::read_to_string(&file);
+            if let Err(err) = fs::remove_file(&file) {
+                eprintln!("'{}' : Failed to remove queued email: {}", user, err);
+                continue;
+            }
+            let Some(queued) = raw
+                .ok()
+                .and_then(|raw| serde_json::from_str::<QueuedEmail>(&raw).ok())
+            else {
+                eprintln!("'{}' : Invalid queued email '{}'", user, file.display());
+                continue;
+            };
+            if !can_push(&queued.git_dir, &user) {
+                eprintln!(
+                    "'{}' : Cannot push to '{}', not emailing about it",
+                    user,
+                    queued.git_dir.display()
+                );
+                continue;
+            }
+            for recipient in watchers(&queued.git_dir) {
+                if let Err(err) = send(&recipient, &queued.subject, &queued.body) {
+                    eprintln!("'{}' : Failed to email '{}' : {}", user, recipient, err);
+                }
+            }
+        }
+    }
+}
+
+fn short_name(name: &str) -> &str {
+    name.strip_prefix("refs/heads/")
+        .or_else(|| name.strip_prefix("refs/tags/"))
+        .unwrap_or(name)
+}
+
+/// Subject and body describing the commits and changes of a push
+pub fn push_summary(git_dir: &Path, path: &Path, updates: &[RefUpdate]) -> (String, String) {
+    let mut body = format!("{} pushed to {}\n", get_username(), path.display());
+    let mut total = 0;
+
+    for update in updates {
+        let name = short_name(&update.name);
+        body.push('\n');
+        if update.is_delete() {
+            body.push_str(&format!("Deleted '{}'\n", name));
+            continue;
+        }
+
+        let log = match update.is_create() {
+            true => {
+                body.push_str(&format!("Created '{}' at {:.7}\n", name, update.new));
+                let exclude = format!("--exclude={}", update.name);
+                run_git(
+                    git_dir,
+                    &[
+                        "log",
+                        "--format=%h %an: %s",
+                        &update.new,
+                        "--not",
+                        &exclude,
+                        "--all",
+                    ],
+                )
+            }
+            false => {
+                body.push_str(&format!(
+                    "Updated '{}' {:.7}..{:.7}\n",
+                    name, update.old, update.new
+                ));
+                let range = format!("{}..{}", update.old, update.new);
+                run_git(git_dir, &["log", "--format=%h %an: %s", &range])
+            }
+        }
+        .unwrap_or_default();
+
+        let commits = log.lines().collect::<Vec<_>>();
+        total += commits.len();
+        for commit in commits.iter().take(MAX_COMMITS) {
+            body.push_str(&format!("  {}\n", commit));
+        }
+        if commits.len() > MAX_COMMITS {
+            body.push_str(&format!("  ... and {} more\n", commits.len() - MAX_COMMITS));
+        }
+
+        if !update.is_create() {
+            let stat = run_git(
+                git_dir,
+                &["diff", "--no-color", "--stat", &update.old, &update.new],
+            )
+            .unwrap_or_default();
+            if !stat.is_empty() {
+                body.push('\n');
+                body.push_str(&stat);
+            }
+        }
+    }
+
+    let names = updates
+        .iter()
+        .map(|update| short_name(&update.name))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let subject = match total {
+        0 => format!("[{}] {} updated {}", path.display(), get_username(), names),
+        1 => format!("[{}] 1 new commit on {}", path.display(), names),
+        n => format!("[{}] {} new commits on {}", path.display(), n, names),
+    };
+    (subject, body)
+}
+
+/// Send a plain text email through the configured relay
+pub fn send(recipient: &str, subject: &str, body: &str) -> Result<(), String> {
+    let smtp = &config().smtp;
+    let message = Message::builder()
+        .from(
+            smtp.from
+                .parse()
+                .map_err(|e| format!("Invalid sender: {}", e))?,
+        )
+        .to(recipient
+            .parse()
+            .map_err(|e| format!("Invalid recipient: {}", e))?)
+        .subject(subject)
+        .header(ContentType::TEXT_PLAIN)
+        .body(body.to_string())
+        .map_err(|e| e.to_string())?;
+
+    let mut transport = match smtp.security {
+        SmtpSecurity::None => SmtpTransport::builder_dangerous(&smtp.host),
+        SmtpSecurity::StartTls => {
+            SmtpTransport::starttls_relay(&smtp.host).map_err(|e| e.to_string())?
+        }
+        SmtpSecurity::Tls => SmtpTransport::relay(&smtp.host).map_err(|e| e.to_string())?,
+    }
+    .port(smtp.port)
+    .timeout(Some(Duration::from_secs(10)));
+    if let (Some(username), Some(password)) = (&smtp.username, &smtp.password) {
+        transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+
+    transport
+        .build()
+        .send(&message)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,142 @@
+//! Append-only logs of events stored as commits on a ref inside a bare repository
+//!
+//! Every event is an empty commit whose message holds `key: value` fields, a blank line
+//! and free text, so the history of merge requests and issues lives, and is backed up,
+//! with the repository itself.
+
+use crate::hooks::NULL_SHA;
+use crate::{run_git, run_git_as_user};
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub author: String,
+    pub time: u64,
+    pub fields: Vec<(String, String)>,
+    pub text: String,
+}
+
+impl Event {
+    pub fn field(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn action(&self) -> &str {
+        self.field("action").unwrap_or_default()
+    }
+}
+
+pub fn parse_title(title: &str) -> Result<String, String> {
+    let title = title.trim();
+    match title.len() {
+        0 => Err(String::from("Title cannot be empty")),
+        201.. => Err(String::from("Title cannot exceed 200 characters")),
+        _ if title.chars().any(char::is_control) => {
+            Err(String::from("Title must be a single line"))
+        }
+        _ => Ok(title.to_string()),
+    }
+}
+
+pub fn parse_comment(comment: &str) -> Result<String, String> {
+    let comment = comment.trim();
+    match comment.len() {
+        0 => Err(String::from("Comment cannot be empty")),
+        10001.. => Err(String::from("Comment cannot exceed 10000 characters")),
+        _ => Ok(comment.to_string()),
+    }
+}
+
+/// Ref holding the events of a numbered item, such as `refs/merge-requests/3`
+pub fn event_ref(prefix: &str, id: u64) -> String {
+    format!("{}/{}", prefix, id)
+}
+
+/// Numbers of every item with events under a ref prefix, in ascending order
+pub fn event_ids(git_dir: &Path, prefix: &str) -> Vec<u64> {
+    let refs = run_git(
+        git_dir,
+        &[
+            "for-each-ref",
+            "--format=%(refname)",
+            &format!("{}/", prefix),
+        ],
+    )
+    .unwrap_or_default();
+    let mut ids = refs
+        .lines()
+        .filter_map(|name| name.rsplit('/').next()?.parse::<u64>().ok())
+        .collect::<Vec<_>>();
+    ids.sort();
+    ids
+}
+
+/// Record an event, creating the log if it does not exist yet
+pub fn append_event(
+    git_dir: &Path,
+    reference: &str,
+    fields: &[(&str, &str)],
+    text: &str,
+) -> Result<(), String> {
+    let mut message = fields
+        .iter()
+        .map(|(key, value)| format!("{}: {}\n", key, value))
+        .collect::<String>();
+    if !text.is_empty() {
+        message.push('\n');
+        message.push_str(text);
+    }
+
+    let tree = run_git(git_dir, &["mktree"])?;
+    let previous = run_git(git_dir, &["rev-parse", "--verify", "--quiet", reference])
+        .ok()
+        .map(|sha| sha.trim().to_string());
+    let mut args = vec!["commit-tree", tree.trim(), "-m", &message];
+    if let Some(previous) = &previous {
+        args.extend(["-p", previous]);
+    }
+    let commit = run_git_as_user(git_dir, &args)?;
+
+    // Fails if another event was recorded meanwhile, rather than losing it
+    let old = previous.as_deref().unwrap_or(NULL_SHA);
+    run_git(git_dir, &["update-ref", reference, commit.trim(), old]).map(|_| ())
+}
+
+/// Every event recorded in a log, oldest first
+pub fn read_events(git_dir: &Path, reference: &str) -> Result<Vec<Event>, String> {
+    let log = run_git(
+        git_dir,
+        &[
+            "log",
+            "--reverse",
+            "--format=%an%x00%at%x00%B%x1e",
+            "--end-of-options",
+            reference,
+        ],
+    )?;
+    let events = log
+        .split('\x1e')
+        .filter_map(|entry| {
+            let mut parts = entry.trim_start_matches('\n').splitn(3, '\0');
+            let author = parts.next()?.to_string();
+            let time = parts.next()?.parse::<u64>().ok()?;
+            let message = parts.next()?;
+            let (header, text) = message.split_once("\n\n").unwrap_or((message, ""));
+            let fields = header
+                .lines()
+                .filter_map(|line| line.split_once(": "))
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect();
+            Some(Event {
+                author,
+                time,
+                fields,
+                text: text.trim_end().to_string(),
+            })
+        })
+        .collect();
+    Ok(events)
+}
@@ -0,0 +1,210 @@
+//! Filters shared by every command selecting repositories
+//!
+//! Each filter is one of:
+//! - a glob, such as `project/*`, including matching repositories and folders
+//! - an excluded glob, such as `!project/old-*`
+//! - a predicate on repository metadata: `size>10M`, `updated<30d`, `is:empty` or
+//!   `is:archived`, which can be negated with a leading `!`
+//!
+//! A repository matches if it matches any included glob (or no globs are given), no
+//! excluded glob, and every predicate.
+
+use crate::stats::{is_empty, last_updated, parse_duration, parse_size, repo_stats};
+use crate::{
+    filter_repos, get_repo_home, is_archived, make_glob_set, matching_repos, parse_repo_glob,
+    unix_time,
+};
+use globset::{Glob, GlobSet};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    fn compare(self, value: u64, other: u64) -> bool {
+        match self {
+            Comparison::Less => value < other,
+            Comparison::LessOrEqual => value <= other,
+            Comparison::Equal => value == other,
+            Comparison::GreaterOrEqual => value >= other,
+            Comparison::Greater => value > other,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Predicate {
+    /// Total size of the repository in bytes
+    Size(Comparison, u64),
+    /// Seconds since the repository was last updated
+    Updated(Comparison, u64),
+    Empty,
+    Archived,
+}
+
+impl Predicate {
+    fn test(&self, git_dir: &Path) -> bool {
+        match self {
+            Predicate::Size(comparison, size) => {
+                repo_stats(git_dir).is_ok_and(|stats| comparison.compare(stats.size(), *size))
+            }
+            Predicate::Updated(comparison, age) => last_updated(git_dir)
+                .is_some_and(|time| comparison.compare(unix_time().saturating_sub(time), *age)),
+            Predicate::Empty => is_empty(git_dir),
+            Predicate::Archived => is_archived(git_dir),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum FilterTerm {
+    Include(Glob),
+    Exclude(Glob),
+    Is(Predicate),
+    IsNot(Predicate),
+}
+
+pub fn parse_repo_filter(filter: &str) -> Result<FilterTerm, String> {
+    let (negated, term) = match filter.strip_prefix('!') {
+        Some(term) => (true, term),
+        None => (false, filter),
+    };
+
+    match parse_predicate(term)? {
+        Some(predicate) if negated => Ok(FilterTerm::IsNot(predicate)),
+        Some(predicate) => Ok(FilterTerm::Is(predicate)),
+        None if negated => Ok(FilterTerm::Exclude(parse_repo_glob(term)?)),
+        None => Ok(FilterTerm::Include(parse_repo_glob(term)?)),
+    }
+}
+
+fn parse_predicate(term: &str) -> Result<Option<Predicate>, String> {
+    match term {
+        "is:empty" => return Ok(Some(Predicate::Empty)),
+        "is:archived" => return Ok(Some(Predicate::Archived)),
+        _ => (),
+    }
+    if let Some(state) = term.strip_prefix("is:") {
+        return Err(format!(
+            "Unknown state 'is:{}', expected 'is:empty' or 'is:archived'",
+            state
+        ));
+    }
+
+    for field in ["size", "updated"] {
+        let Some(rest) = term.strip_prefix(field) else {
+            continue;
+        };
+        let (comparison, value) = if let Some(value) = rest.strip_prefix("<=") {
+            (Comparison::LessOrEqual, value)
+        } else if let Some(value) = rest.strip_prefix(">=") {
+            (Comparison::GreaterOrEqual, value)
+        } else if let Some(value) = rest.strip_prefix('<') {
+            (Comparison::Less, value)
+        } else if let Some(value) = rest.strip_prefix('>') {
+            (Comparison::Greater, value)
+        } else if let Some(value) = rest.strip_prefix('=') {
+            (Comparison::Equal, value)
+        } else {
+            continue;
+        };
+        return match field {
+            "size" => Ok(Some(Predicate::Size(comparison, parse_size(value)?))),
+            _ => Ok(Some(Predicate::Updated(comparison, parse_duration(value)?))),
+        };
+    }
+    Ok(None)
+}
+
+/// Combination of filter terms, matched against repositories in the user's home
+pub struct RepoFilter {
+    include: GlobSet,
+    exclude: GlobSet,
+    predicates: Vec<(Predicate, bool)>,
+    repo_home: PathBuf,
+}
+
+impl RepoFilter {
+    pub fn new(terms: &[FilterTerm]) -> RepoFilter {
+        let include = terms.iter().filter_map(|term| match term {
+            FilterTerm::Include(glob) => Some(glob),
+            _ => None,
+        });
+        let exclude = terms.iter().filter_map(|term| match term {
+            FilterTerm::Exclude(glob) => Some(glob),
+            _ => None,
+        });
+        let predicates = terms
+            .iter()
+            .filter_map(|term| match term {
+                FilterTerm::Is(predicate) => Some((predicate.clone(), true)),
+                FilterTerm::IsNot(predicate) => Some((predicate.clone(), false)),
+                _ => None,
+            })
+            .collect();
+
+        RepoFilter {
+            include: make_glob_set(include),
+            exclude: make_glob_set(exclude),
+            predicates,
+            repo_home: get_repo_home(),
+        }
+    }
+
+    pub fn has_includes(&self) -> bool {
+        !self.include.is_empty()
+    }
+
+    /// Whether the filter explicitly asks for archived repositories
+    pub fn selects_archived(&self) -> bool {
+        self.predicates
+            .iter()
+            .any(|(predicate, expected)| matches!(predicate, Predicate::Archived) && *expected)
+    }
+
+    /// Whether a path, or any folder containing it, matches an included glob and is not
+    /// excluded
+    pub fn includes(&self, path: &Path) -> bool {
+        let included =
+            self.include.is_empty() || path.ancestors().any(|p| self.include.is_match(p));
+        included && !self.excludes(path)
+    }
+
+    /// Whether a path, or any folder containing it, is excluded
+    pub fn excludes(&self, path: &Path) -> bool {
+        path.ancestors().any(|p| self.exclude.is_match(p))
+    }
+
+    /// Whether a repository passes every predicate and is not excluded
+    pub fn accepts(&self, path: &Path) -> bool {
+        if self.excludes(path) {
+            return false;
+        }
+        let git_dir = self.repo_home.join(path);
+        self.predicates
+            .iter()
+            .all(|(predicate, expected)| predicate.test(&git_dir) == *expected)
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        self.includes(path) && self.accepts(path)
+    }
+
+    /// All repositories matching the filter, expanding any matched folders
+    pub fn repos(&self) -> Vec<PathBuf> {
+        let candidates = match self.has_includes() {
+            true => matching_repos(&self.include),
+            false => filter_repos(None, false, |_| true).collect(),
+        };
+        candidates
+            .into_iter()
+            .filter(|path| self.accepts(path))
+            .collect()
+    }
+}
@@ -0,0 +1,165 @@
+use crate::ci::queue_jobs;
+use crate::email::notify_push;
+use crate::webhooks::{notify, RepoEvent};
+use crate::{get_repo_options, random_hex, relative_repo_path, run_git};
+use std::io::{self, BufRead};
+use std::path::Path;
+
+mod archive;
+mod managed;
+mod org;
+mod policy;
+mod protection;
+mod secrets;
+mod signatures;
+mod size;
+
+/// Object name git uses for the missing side of a ref creation or deletion
+pub const NULL_SHA: &str = "0000000000000000000000000000000000000000";
+
+/// A check run before accepting a push, returning the reasons it was rejected
+type Check = fn(&Path, &[RefUpdate]) -> Result<(), Vec<String>>;
+
+/// Checks repositories opt into, by name, with the `checks` command
+const OPTIONAL_CHECKS: &[(&str, Check)] = &[
+    ("secrets", secrets::check),
+    ("signed-push", signatures::check_push),
+    ("signed-commits", signatures::check_commits),
+];
+
+/// Optional checks of what only a push carries, left out when the server moves refs itself
+const PUSH_ONLY_CHECKS: &[&str] = &["signed-push"];
+
+/// A single ref update received by a push
+#[derive(Debug, Clone)]
+pub struct RefUpdate {
+    pub old: String,
+    pub new: String,
+    pub name: String,
+}
+
+impl RefUpdate {
+    pub fn is_create(&self) -> bool {
+        self.old == NULL_SHA
+    }
+
+    pub fn is_delete(&self) -> bool {
+        self.new == NULL_SHA
+    }
+
+    pub fn branch(&self) -> Option<&str> {
+        self.name.strip_prefix("refs/heads/")
+    }
+}
+
+/// Read the ref updates passed to a receive hook on stdin
+pub fn read_ref_updates() -> Vec<RefUpdate> {
+    io::stdin()
+        .lock()
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            Some(RefUpdate {
+                old: parts.next()?.to_string(),
+                new: parts.next()?.to_string(),
+                name: parts.next()?.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Checks every push has to pass
+const CHECKS: &[Check] = &[
+    org::check,
+    archive::check,
+    managed::check,
+    protection::check,
+    size::check,
+    policy::check,
+];
+
+/// Run every check on a push, returning the reasons it was rejected
+pub fn pre_receive(git_dir: &Path, updates: &[RefUpdate]) -> Result<(), Vec<String>> {
+    for check in CHECKS {
+        check(git_dir, updates)?;
+    }
+    protection::check_statuses(git_dir, updates)?;
+    let enabled = enabled_checks(git_dir);
+    for (name, check) in OPTIONAL_CHECKS {
+        if enabled.iter().any(|e| e == name) {
+            check(git_dir, updates)?;
+        }
+    }
+    Ok(())
+}
+
+/// Run the checks of a push on a ref a server command moves itself, such as when merging or
+/// renaming branches
+///
+/// There is no push certificate to check. When the server created the new commit itself,
+/// such as a merge commit, it has no statuses or signature yet, so the command checks the
+/// statuses of the commits it merges and only those need signatures.
+pub fn check_update(git_dir: &Path, update: &RefUpdate, created: bool) -> Result<(), Vec<String>> {
+    let updates = std::slice::from_ref(update);
+    for check in CHECKS {
+        check(git_dir, updates)?;
+    }
+    if !created {
+        protection::check_statuses(git_dir, updates)?;
+    }
+    let enabled = enabled_checks(git_dir);
+    for (name, check) in OPTIONAL_CHECKS {
+        if !enabled.iter().any(|e| e == name) || PUSH_ONLY_CHECKS.contains(name) {
+            continue;
+        }
+        match *name {
+            "signed-commits" if created => {
+                signatures::check_server_commits(git_dir, updates, &update.new)?
+            }
+            _ => check(git_dir, updates)?,
+        }
+    }
+    Ok(())
+}
+
+/// Names of the optional checks a repository has enabled
+pub fn enabled_checks(git_dir: &Path) -> Vec<String> {
+    get_repo_options(git_dir, "check")
+}
+
+/// Prepare a repository for a check which has just been enabled
+pub fn prepare_check(git_dir: &Path, name: &str) -> Result<(), String> {
+    // Pushes can only be signed if the repository hands out nonces to sign
+    if name == "signed-push" && run_git(git_dir, &["config", "receive.certNonceSeed"]).is_err() {
+        run_git(
+            git_dir,
+            &["config", "receive.certNonceSeed", &random_hex(32)],
+        )?;
+    }
+    Ok(())
+}
+
+pub fn parse_check(name: &str) -> Result<String, String> {
+    match OPTIONAL_CHECKS.iter().any(|(check, _)| *check == name) {
+        true => Ok(name.to_string()),
+        false => Err(format!(
+            "Unknown check, available checks are: {}",
+            OPTIONAL_CHECKS
+                .iter()
+                .map(|(check, _)| *check)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+/// Notify others of a push once it has been accepted
+pub fn post_receive(git_dir: &Path, updates: &[RefUpdate]) {
+    let Some(path) = relative_repo_path(git_dir) else {
+        return;
+    };
+    notify(&path, RepoEvent::Push(updates));
+    notify_push(git_dir, &path, updates);
+    queue_jobs(git_dir, updates);
+}
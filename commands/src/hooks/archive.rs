@@ -0,0 +1,12 @@
+use super::RefUpdate;
+use crate::is_archived;
+use std::path::Path;
+
+pub fn check(git_dir: &Path, _updates: &[RefUpdate]) -> Result<(), Vec<String>> {
+    if is_archived(git_dir) {
+        return Err(vec![String::from(
+            "Repository is archived and read-only, unarchive it to push",
+        )]);
+    }
+    Ok(())
+}
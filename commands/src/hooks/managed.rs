@@ -0,0 +1,26 @@
+use super::RefUpdate;
+use std::path::Path;
+
+/// Refs only changed by server commands, which pushes must not tamper with
+const MANAGED_PREFIXES: &[&str] = &[
+    "refs/merge-requests/",
+    "refs/issues/",
+    "refs/notes/statuses",
+];
+
+pub fn check(_git_dir: &Path, updates: &[RefUpdate]) -> Result<(), Vec<String>> {
+    let problems = updates
+        .iter()
+        .filter(|update| MANAGED_PREFIXES.iter().any(|p| update.name.starts_with(p)))
+        .map(|update| {
+            format!(
+                "'{}' is managed by the server and cannot be pushed",
+                update.name
+            )
+        })
+        .collect::<Vec<_>>();
+    match problems.is_empty() {
+        true => Ok(()),
+        false => Err(problems),
+    }
+}
@@ -0,0 +1,15 @@
+use super::RefUpdate;
+use crate::orgs::{require_role, Role};
+use crate::relative_repo_path;
+use std::path::Path;
+
+/// Reject pushes to repositories of organisations from members who can only read
+///
+/// Pushes to repositories that are neither the user's nor their organisations' are rejected
+/// too, as in gateway mode the shared account could write to any of them.
+pub fn check(git_dir: &Path, _updates: &[RefUpdate]) -> Result<(), Vec<String>> {
+    let Some(path) = relative_repo_path(git_dir) else {
+        return Err(vec![String::from("Not one of your repositories")]);
+    };
+    require_role(&path, Role::Write).map_err(|err| vec![err])
+}
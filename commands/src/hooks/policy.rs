@@ -0,0 +1,142 @@
+use super::RefUpdate;
+use crate::policies::{repo_policies, Policy};
+use crate::{relative_repo_path, run_git};
+use regex::Regex;
+use std::path::Path;
+
+/// Violations reported before the rest are summarised
+const MAX_REPORTED: usize = 50;
+
+struct Commit {
+    sha: String,
+    author: String,
+    committer: String,
+    message: String,
+    is_merge: bool,
+}
+
+/// Reject pushes adding commits which break the policies of the repository
+pub fn check(git_dir: &Path, updates: &[RefUpdate]) -> Result<(), Vec<String>> {
+    let Some(path) = relative_repo_path(git_dir) else {
+        return Ok(());
+    };
+    let policies = repo_policies(&path).map_err(|e| vec![e])?;
+    let tips = updates
+        .iter()
+        .filter(|update| !update.is_delete())
+        .map(|update| update.new.as_str())
+        .collect::<Vec<_>>();
+    if policies.is_empty() || tips.is_empty() {
+        return Ok(());
+    }
+
+    let mut problems = Vec::new();
+    for commit in new_commits(git_dir, &tips)? {
+        for policy in &policies {
+            problems.extend(violations(git_dir, policy, &commit));
+        }
+    }
+    if problems.is_empty() {
+        return Ok(());
+    }
+
+    let total = problems.len();
+    problems.truncate(MAX_REPORTED);
+    if total > MAX_REPORTED {
+        problems.push(format!("... and {} more violations", total - MAX_REPORTED));
+    }
+    problems.push(String::from(
+        "Rewrite the commits to follow the policies, such as with 'git rebase -i'",
+    ));
+    Err(problems)
+}
+
+/// Commits the repository does not have yet, oldest first
+fn new_commits(git_dir: &Path, tips: &[&str]) -> Result<Vec<Commit>, Vec<String>> {
+    let mut args = vec![
+        "log",
+        "--reverse",
+        "--format=%H%x1f%P%x1f%ae%x1f%ce%x1f%B%x1e",
+    ];
+    args.extend(tips);
+    args.extend(["--not", "--all"]);
+    let log = run_git(git_dir, &args).map_err(|e| vec![e])?;
+
+    Ok(log
+        .split('\x1e')
+        .filter_map(|record| {
+            let fields = record
+                .trim_start_matches('\n')
+                .split('\x1f')
+                .collect::<Vec<_>>();
+            let [sha, parents, author, committer, message] = fields[..] else {
+                return None;
+            };
+            Some(Commit {
+                sha: sha.to_string(),
+                author: author.to_string(),
+                committer: committer.to_string(),
+                message: message.trim().to_string(),
+                is_merge: parents.split(' ').count() > 1,
+            })
+        })
+        .collect())
+}
+
+fn violations(git_dir: &Path, policy: &Policy, commit: &Commit) -> Vec<String> {
+    let mut problems = Vec::new();
+    let subject = commit.message.lines().next().unwrap_or_default();
+    let mut report = |problem: String| {
+        problems.push(format!("{:.7} {} : {}", commit.sha, subject, problem));
+    };
+
+    // Merge commits get their message from git, so only their authors are checked
+    if !commit.is_merge {
+        if let Some(pattern) = &policy.message_pattern {
+            if !Regex::new(pattern).is_ok_and(|regex| regex.is_match(&commit.message)) {
+                report(format!("message does not match '{}'", pattern));
+            }
+        }
+        if let Some(max_length) = policy.max_subject_length {
+            let length = subject.chars().count();
+            if length > max_length {
+                report(format!(
+                    "subject is {} characters, longer than {}",
+                    length, max_length
+                ));
+            }
+        }
+    }
+    for (role, email) in [("author", &commit.author), ("committer", &commit.committer)] {
+        if !policy.allows_domain(email) {
+            report(format!(
+                "{} email '{}' is not at {}",
+                role,
+                email,
+                policy.email_domains.join(", ")
+            ));
+        }
+    }
+    if !policy.forbidden_paths.is_empty() {
+        let changed = run_git(
+            git_dir,
+            &[
+                "-c",
+                "core.quotePath=false",
+                "diff-tree",
+                "--no-commit-id",
+                "--name-only",
+                "--no-renames",
+                "--diff-filter=d",
+                "-r",
+                "--root",
+                &commit.sha,
+            ],
+        )
+        .unwrap_or_default();
+        for file in changed.lines().filter(|file| policy.is_forbidden(file)) {
+            report(format!("changes '{}', which is forbidden", file));
+        }
+    }
+    problems
+}
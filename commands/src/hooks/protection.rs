@@ -0,0 +1,72 @@
+use super::RefUpdate;
+use crate::config::config;
+use crate::run_git;
+use crate::status::missing_statuses;
+use std::path::Path;
+
+/// Reject deleting or rewriting the history of protected branches
+pub fn check(git_dir: &Path, updates: &[RefUpdate]) -> Result<(), Vec<String>> {
+    let protection = &config().protection;
+    let mut problems = Vec::new();
+
+    for update in updates {
+        let Some(branch) = update.branch() else {
+            continue;
+        };
+        if !protection.is_protected(branch) {
+            continue;
+        }
+        if update.is_delete() {
+            problems.push(format!("'{}' is protected and cannot be deleted", branch));
+            continue;
+        }
+        if !update.is_create() && !is_fast_forward(git_dir, update) {
+            problems.push(format!(
+                "'{}' is protected and cannot be force pushed",
+                branch
+            ));
+        }
+    }
+
+    match problems.is_empty() {
+        true => Ok(()),
+        false => Err(problems),
+    }
+}
+
+/// Reject moving protected branches to commits without the required statuses
+pub fn check_statuses(git_dir: &Path, updates: &[RefUpdate]) -> Result<(), Vec<String>> {
+    let protection = &config().protection;
+    let mut problems = Vec::new();
+
+    for update in updates.iter().filter(|update| !update.is_delete()) {
+        let Some(branch) = update.branch() else {
+            continue;
+        };
+        if !protection.is_protected(branch) {
+            continue;
+        }
+        let missing = missing_statuses(git_dir, &update.new, &protection.required_statuses);
+        if !missing.is_empty() {
+            problems.push(format!(
+                "'{}' is protected and requires '{}' to succeed for {:.7} first",
+                branch,
+                missing.join("', '"),
+                update.new
+            ));
+        }
+    }
+
+    match problems.is_empty() {
+        true => Ok(()),
+        false => Err(problems),
+    }
+}
+
+fn is_fast_forward(git_dir: &Path, update: &RefUpdate) -> bool {
+    run_git(
+        git_dir,
+        &["merge-base", "--is-ancestor", &update.old, &update.new],
+    )
+    .is_ok()
+}
@@ -0,0 +1,179 @@
+use super::RefUpdate;
+use crate::run_git;
+use globset::Glob;
+use regex::Regex;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// File in the pushed tree listing paths to skip, or values which are not secrets
+pub const ALLOWLIST_FILE: &str = ".secrets-allowlist";
+
+/// Findings reported before the rest are summarised
+const MAX_FINDINGS: usize = 20;
+
+const PATTERNS: &[(&str, &str)] = &[
+    (
+        "private key",
+        r"-----BEGIN [A-Z ]*PRIVATE KEY( BLOCK)?-----",
+    ),
+    ("AWS access key", r"\b(AKIA|ASIA)[0-9A-Z]{16}\b"),
+    (
+        "AWS secret key",
+        r#"(?i)aws_?secret_?access_?key['"]?\s*[:=]\s*['"]?[A-Za-z0-9/+]{40}\b"#,
+    ),
+    (
+        "GitHub token",
+        r"\b(gh[pousr]_[A-Za-z0-9]{36,}|github_pat_[A-Za-z0-9_]{60,})\b",
+    ),
+    ("GitLab token", r"\bglpat-[A-Za-z0-9_\-]{20,}"),
+    ("Slack token", r"\bxox[abprs]-[A-Za-z0-9\-]{10,}"),
+    ("Stripe key", r"\b[rs]k_live_[A-Za-z0-9]{24,}\b"),
+    ("Google API key", r"\bAIza[0-9A-Za-z_\-]{35}\b"),
+];
+
+struct Finding {
+    commit: String,
+    path: String,
+    line: usize,
+    kind: &'static str,
+    value: String,
+}
+
+fn patterns() -> &'static [(&'static str, Regex)] {
+    static COMPILED: OnceLock<Vec<(&str, Regex)>> = OnceLock::new();
+    COMPILED.get_or_init(|| {
+        PATTERNS
+            .iter()
+            .map(|(kind, pattern)| (*kind, Regex::new(pattern).unwrap()))
+            .collect()
+    })
+}
+
+/// Reject pushes adding lines which look like credentials
+pub fn check(git_dir: &Path, updates: &[RefUpdate]) -> Result<(), Vec<String>> {
+    let tips = updates
+        .iter()
+        .filter(|update| !update.is_delete())
+        .map(|update| update.new.as_str())
+        .collect::<Vec<_>>();
+    if tips.is_empty() {
+        return Ok(());
+    }
+
+    // Only commits the repository does not have yet, as a single patch per commit
+    let mut args = vec![
+        "-c",
+        "core.quotePath=false",
+        "log",
+        "--patch",
+        "--unified=0",
+        "--no-color",
+        "--no-ext-diff",
+        "--no-renames",
+        "--format=%x00%H",
+    ];
+    args.extend(&tips);
+    args.extend(["--not", "--all"]);
+    let patch = run_git(git_dir, &args).map_err(|e| vec![e])?;
+
+    let allowlist = tips
+        .iter()
+        .flat_map(|tip| load_allowlist(git_dir, tip))
+        .collect::<Vec<_>>();
+    let findings = scan_patch(&patch)
+        .into_iter()
+        .filter(|finding| !is_allowed(&allowlist, finding))
+        .collect::<Vec<_>>();
+    if findings.is_empty() {
+        return Ok(());
+    }
+
+    let mut problems = findings
+        .iter()
+        .take(MAX_FINDINGS)
+        .map(|finding| {
+            format!(
+                "'{}' line {} ({:.7}) : possible {}",
+                finding.path, finding.line, finding.commit, finding.kind
+            )
+        })
+        .collect::<Vec<_>>();
+    if findings.len() > MAX_FINDINGS {
+        problems.push(format!(
+            "... and {} more possible secrets",
+            findings.len() - MAX_FINDINGS
+        ));
+    }
+    problems.push(format!(
+        "Remove the secrets from history and revoke them, or list paths or values which are not secrets in {}",
+        ALLOWLIST_FILE
+    ));
+    Err(problems)
+}
+
+/// Find secrets in the lines added by a patch, noting where they were added
+fn scan_patch(patch: &str) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut commit = "";
+    let mut path = None;
+    let mut line = 0;
+    // Added lines can look like file headers, so only read those before the first hunk
+    let mut in_header = false;
+
+    for text in patch.lines() {
+        if let Some(sha) = text.strip_prefix('\0') {
+            commit = sha;
+            path = None;
+        } else if text.starts_with("diff --git ") {
+            in_header = true;
+            path = None;
+        } else if in_header {
+            if let Some(name) = text.strip_prefix("+++ ") {
+                path = name.strip_prefix("b/");
+            } else if let Some(hunk) = text.strip_prefix("@@ ") {
+                line = hunk_start(hunk).unwrap_or(0);
+                in_header = false;
+            }
+        } else if let Some(hunk) = text.strip_prefix("@@ ") {
+            line = hunk_start(hunk).unwrap_or(0);
+        } else if let (Some(added), Some(path)) = (text.strip_prefix('+'), path) {
+            for (kind, pattern) in patterns() {
+                if let Some(found) = pattern.find(added) {
+                    findings.push(Finding {
+                        commit: commit.to_string(),
+                        path: path.to_string(),
+                        line,
+                        kind,
+                        value: found.as_str().to_string(),
+                    });
+                }
+            }
+            line += 1;
+        }
+    }
+    findings
+}
+
+/// First line of the new side of a hunk header, such as `-12,3 +14,5 @@`
+fn hunk_start(hunk: &str) -> Option<usize> {
+    let new = hunk.split_whitespace().find(|part| part.starts_with('+'))?;
+    new[1..].split(',').next()?.parse().ok()
+}
+
+fn load_allowlist(git_dir: &Path, commit: &str) -> Vec<String> {
+    let file = format!("{}:{}", commit, ALLOWLIST_FILE);
+    run_git(git_dir, &["cat-file", "blob", &file])
+        .unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+fn is_allowed(allowlist: &[String], finding: &Finding) -> bool {
+    allowlist.iter().any(|entry| {
+        *entry == finding.value
+            || Glob::new(entry).is_ok_and(|glob| glob.compile_matcher().is_match(&finding.path))
+    })
+}
@@ -0,0 +1,153 @@
+use super::RefUpdate;
+use crate::config::config;
+use crate::get_username;
+use crate::run_git;
+use crate::signing::{Verifier, SIGNING_KEYS_FILE};
+use std::env;
+use std::path::Path;
+
+/// Commits without a good signature reported before the rest are summarised
+const MAX_REPORTED: usize = 20;
+
+fn verifier() -> Result<Verifier, Vec<String>> {
+    let verifier = Verifier::new(&get_username()).map_err(|e| vec![e])?;
+    match verifier.has_keys() {
+        true => Ok(verifier),
+        false => Err(vec![format!(
+            "'{}' has no signing keys, ask an administrator to add them to ~/{}",
+            get_username(),
+            SIGNING_KEYS_FILE
+        )]),
+    }
+}
+
+/// Reject pushes without a push certificate signed by one of the pusher's keys
+pub fn check_push(git_dir: &Path, updates: &[RefUpdate]) -> Result<(), Vec<String>> {
+    let Ok(certificate) = env::var("GIT_PUSH_CERT") else {
+        return Err(vec![String::from(
+            "Pushes to this repository must be signed, push with 'git push --signed'",
+        )]);
+    };
+    if env::var("GIT_PUSH_CERT_NONCE_STATUS").as_deref() != Ok("OK") {
+        return Err(vec![String::from(
+            "Push certificate is stale or was made for another push, push again",
+        )]);
+    }
+
+    let raw = run_git(git_dir, &["cat-file", "blob", &certificate]).map_err(|e| vec![e])?;
+    let Some(start) = raw.find("-----BEGIN ") else {
+        return Err(vec![String::from("Push certificate is not signed")]);
+    };
+    let (payload, signature) = raw.split_at(start);
+    if !verifier()?.verify(payload, signature) {
+        return Err(vec![format!(
+            "Push certificate is not signed by a key of '{}'",
+            get_username()
+        )]);
+    }
+
+    // The certificate must vouch for every ref the push changes
+    let problems = updates
+        .iter()
+        .filter(|update| {
+            let line = format!("{} {} {}", update.old, update.new, update.name);
+            !payload.lines().any(|l| l == line)
+        })
+        .map(|update| format!("'{}' is not covered by the push certificate", update.name))
+        .collect::<Vec<_>>();
+    match problems.is_empty() {
+        true => Ok(()),
+        false => Err(problems),
+    }
+}
+
+/// Reject pushes adding commits to protected branches without a good signature by a key of
+/// any user, so commits by others can be pushed as long as their author signed them
+pub fn check_commits(git_dir: &Path, updates: &[RefUpdate]) -> Result<(), Vec<String>> {
+    check_new_commits(git_dir, updates, None)
+}
+
+/// As `check_commits`, for a branch the server moves to a commit it created and cannot sign,
+/// such as a merge commit, so only the commits it brings onto the branch need signatures
+pub fn check_server_commits(
+    git_dir: &Path,
+    updates: &[RefUpdate],
+    created: &str,
+) -> Result<(), Vec<String>> {
+    check_new_commits(git_dir, updates, Some(created))
+}
+
+fn check_new_commits(
+    git_dir: &Path,
+    updates: &[RefUpdate],
+    created: Option<&str>,
+) -> Result<(), Vec<String>> {
+    let protection = &config().protection;
+    let protected = updates
+        .iter()
+        .filter(|update| !update.is_delete())
+        .filter(|update| update.branch().is_some_and(|b| protection.is_protected(b)))
+        .collect::<Vec<_>>();
+    if protected.is_empty() {
+        return Ok(());
+    }
+
+    // Commits already on a protected branch were checked when they got there, while those
+    // only on other branches never were
+    let mut args = vec![String::from("rev-list")];
+    for update in &protected {
+        args.push(update.new.clone());
+        if !update.is_create() {
+            args.push(format!("^{}", update.old));
+        }
+    }
+    let branches = run_git(
+        git_dir,
+        &[
+            "for-each-ref",
+            "--format=%(objectname) %(refname:short)",
+            "refs/heads/",
+        ],
+    )
+    .map_err(|e| vec![e])?;
+    for (commit, branch) in branches.lines().filter_map(|line| line.split_once(' ')) {
+        if protection.is_protected(branch) {
+            args.push(format!("^{}", commit));
+        }
+    }
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    let commits = run_git(git_dir, &args).map_err(|e| vec![e])?;
+    let verifier = Verifier::all_users(&get_username()).map_err(|e| vec![e])?;
+    let unsigned = commits
+        .lines()
+        .filter(|commit| Some(*commit) != created)
+        .filter(|commit| !verifier.verify_commit(git_dir, commit))
+        .collect::<Vec<_>>();
+    if unsigned.is_empty() {
+        return Ok(());
+    }
+
+    let mut problems = unsigned
+        .iter()
+        .take(MAX_REPORTED)
+        .map(|commit| {
+            let subject =
+                run_git(git_dir, &["log", "-1", "--format=%s", commit]).unwrap_or_default();
+            format!(
+                "{:.7} {} : not signed by a key of any user",
+                commit,
+                subject.trim()
+            )
+        })
+        .collect::<Vec<_>>();
+    if unsigned.len() > MAX_REPORTED {
+        problems.push(format!(
+            "... and {} more unsigned commits",
+            unsigned.len() - MAX_REPORTED
+        ));
+    }
+    problems.push(String::from(
+        "Commits on protected branches must be signed, sign them with 'git commit -S' or 'git rebase --exec \"git commit --amend --no-edit -S\"'",
+    ));
+    Err(problems)
+}
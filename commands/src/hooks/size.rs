@@ -0,0 +1,133 @@
+use super::RefUpdate;
+use crate::config::size_limits;
+use crate::stats::format_size;
+use crate::{git_command, relative_repo_path, run_git};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::process::Stdio;
+use std::thread;
+
+/// Oversized files reported before the rest are summarised
+const MAX_REPORTED: usize = 20;
+
+/// Reject pushes adding files or data beyond the size limits of the repository
+pub fn check(git_dir: &Path, updates: &[RefUpdate]) -> Result<(), Vec<String>> {
+    let Some(path) = relative_repo_path(git_dir) else {
+        return Ok(());
+    };
+    let limits = size_limits(&path);
+    if limits.max_blob.is_none() && limits.max_push.is_none() {
+        return Ok(());
+    }
+    let tips = updates
+        .iter()
+        .filter(|update| !update.is_delete())
+        .map(|update| update.new.as_str())
+        .collect::<Vec<_>>();
+    if tips.is_empty() {
+        return Ok(());
+    }
+
+    // Only objects the repository does not have yet, with the path they were first seen at
+    let mut args = vec!["rev-list", "--objects"];
+    args.extend(&tips);
+    args.extend(["--not", "--all"]);
+    let listed = run_git(git_dir, &args).map_err(|e| vec![e])?;
+    let mut paths = HashMap::new();
+    for line in listed.lines() {
+        let (sha, name) = line.split_once(' ').unwrap_or((line, ""));
+        paths.entry(sha.to_string()).or_insert(name.to_string());
+    }
+    let objects = object_sizes(git_dir, paths.keys().map(String::as_str))?;
+
+    let mut problems = Vec::new();
+    if let Some(max_blob) = limits.max_blob {
+        let mut large = objects
+            .iter()
+            .filter(|object| object.kind == "blob" && object.size > max_blob)
+            .collect::<Vec<_>>();
+        large.sort_by_key(|object| u64::MAX - object.size);
+        for object in large.iter().take(MAX_REPORTED) {
+            problems.push(format!(
+                "'{}' is {}, larger than the limit of {}",
+                paths.get(&object.sha).map_or("?", |name| name.as_str()),
+                format_size(object.size),
+                format_size(max_blob)
+            ));
+        }
+        if large.len() > MAX_REPORTED {
+            problems.push(format!(
+                "... and {} more large files",
+                large.len() - MAX_REPORTED
+            ));
+        }
+    }
+    if let Some(max_push) = limits.max_push {
+        let total = objects.iter().map(|object| object.disk_size).sum::<u64>();
+        if total > max_push {
+            problems.push(format!(
+                "Push adds {}, more than the limit of {}",
+                format_size(total),
+                format_size(max_push)
+            ));
+        }
+    }
+
+    match problems.is_empty() {
+        true => Ok(()),
+        false => {
+            problems.push(String::from(
+                "Store large files with Git LFS instead, such as with 'git lfs track \"*.mp4\"', and rewrite the commits adding them",
+            ));
+            Err(problems)
+        }
+    }
+}
+
+struct Object {
+    sha: String,
+    kind: String,
+    size: u64,
+    disk_size: u64,
+}
+
+/// Look up the type and sizes of many objects with a single git process
+fn object_sizes<'a>(
+    git_dir: &Path,
+    shas: impl Iterator<Item = &'a str>,
+) -> Result<Vec<Object>, Vec<String>> {
+    let input = shas.map(|sha| format!("{}\n", sha)).collect::<String>();
+    let mut child = git_command(git_dir)
+        .args([
+            "cat-file",
+            "--batch-check=%(objectname) %(objecttype) %(objectsize) %(objectsize:disk)",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| vec![format!("Failed to run git: {}", e)])?;
+    let mut stdin = child.stdin.take().unwrap();
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child
+        .wait_with_output()
+        .map_err(|e| vec![format!("Failed to run git: {}", e)])?;
+    writer.join().ok();
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let fields = line.split(' ').collect::<Vec<_>>();
+            let [sha, kind, size, disk_size] = fields[..] else {
+                return None;
+            };
+            Some(Object {
+                sha: sha.to_string(),
+                kind: kind.to_string(),
+                size: size.parse().ok()?,
+                disk_size: disk_size.parse().ok()?,
+            })
+        })
+        .collect())
+}
@@ -0,0 +1,83 @@
+use crate::events::{event_ids, event_ref, read_events, Event};
+use crate::run_git;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Refs holding the events of every issue, numbered from 1
+pub const ISSUE_PREFIX: &str = "refs/issues";
+
+pub struct Issue {
+    pub id: u64,
+    pub title: String,
+    pub open: bool,
+    pub events: Vec<Event>,
+}
+
+impl Issue {
+    pub fn load(git_dir: &Path, id: u64) -> Result<Issue, String> {
+        let events = read_events(git_dir, &event_ref(ISSUE_PREFIX, id))
+            .map_err(|_| format!("No issue #{}", id))?;
+        let opened = events
+            .first()
+            .filter(|event| event.action() == "open")
+            .ok_or(format!("Issue #{} is corrupt", id))?;
+        let open = events
+            .iter()
+            .rev()
+            .map(Event::action)
+            .find(|action| *action != "comment")
+            .is_some_and(|action| action != "close");
+
+        Ok(Issue {
+            id,
+            title: opened.field("title").unwrap_or_default().to_string(),
+            open,
+            events,
+        })
+    }
+
+    pub fn comments(&self) -> usize {
+        self.events
+            .iter()
+            .filter(|e| e.action() == "comment")
+            .count()
+    }
+}
+
+pub fn issues(git_dir: &Path) -> Vec<Issue> {
+    event_ids(git_dir, ISSUE_PREFIX)
+        .into_iter()
+        .filter_map(|id| Issue::load(git_dir, id).ok())
+        .collect()
+}
+
+/// Number of open issues, read from a single log of every issue rather than one per issue
+pub fn open_issues(git_dir: &Path) -> usize {
+    let log = run_git(
+        git_dir,
+        &[
+            "log",
+            "--topo-order",
+            "--source",
+            "--format=%S%x00%B%x1e",
+            &format!("--glob={}/*", ISSUE_PREFIX),
+        ],
+    )
+    .unwrap_or_default();
+
+    // Newest events come first, so the first action other than a comment decides the state
+    let mut open = HashMap::new();
+    for entry in log.split('\x1e') {
+        let Some((reference, message)) = entry.trim_start_matches('\n').split_once('\0') else {
+            continue;
+        };
+        let action = message
+            .lines()
+            .find_map(|line| line.strip_prefix("action: "))
+            .unwrap_or_default();
+        if action != "comment" {
+            open.entry(reference).or_insert(action != "close");
+        }
+    }
+    open.into_values().filter(|open| *open).count()
+}
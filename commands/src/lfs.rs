@@ -0,0 +1,99 @@
+//! Git LFS objects, stored by their SHA-256 in `lfs/objects/` inside the bare repository
+//!
+//! This is the layout git-lfs itself uses, so the objects move, archive and get deleted
+//! along with the repository.
+
+use crate::random_hex;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+pub const LFS_FOLDER: &str = "lfs";
+
+pub fn parse_oid(oid: &str) -> Result<String, String> {
+    match oid.len() == 64 && oid.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f')) {
+        true => Ok(oid.to_string()),
+        false => Err(format!("Invalid object ID '{}'", oid)),
+    }
+}
+
+pub fn object_path(git_dir: &Path, oid: &str) -> PathBuf {
+    git_dir
+        .join(LFS_FOLDER)
+        .join("objects")
+        .join(&oid[0..2])
+        .join(&oid[2..4])
+        .join(oid)
+}
+
+/// Size of a stored object, if the repository has it
+pub fn object_size(git_dir: &Path, oid: &str) -> Option<u64> {
+    fs::metadata(object_path(git_dir, oid))
+        .ok()
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+}
+
+/// Number and total size of the objects stored for a repository
+pub fn usage(git_dir: &Path) -> (u64, u64) {
+    let mut objects = 0;
+    let mut size = 0;
+    let entries = WalkDir::new(git_dir.join(LFS_FOLDER).join("objects"))
+        .into_iter()
+        .filter_map(Result::ok);
+    for entry in entries.filter(|entry| entry.file_type().is_file()) {
+        objects += 1;
+        size += entry.metadata().map(|m| m.len()).unwrap_or(0);
+    }
+    (objects, size)
+}
+
+/// Store an object read from a stream, checking it matches its ID and expected size
+pub fn store_object(
+    git_dir: &Path,
+    oid: &str,
+    size: u64,
+    data: &mut impl Read,
+) -> Result<(), String> {
+    let tmp_dir = git_dir.join(LFS_FOLDER).join("tmp");
+    fs::create_dir_all(&tmp_dir).map_err(|e| e.to_string())?;
+    let tmp_path = tmp_dir.join(format!("{}-{}", oid, random_hex(4)));
+
+    let result = write_verified(&tmp_path, oid, size, data).and_then(|()| {
+        let path = object_path(git_dir, oid);
+        fs::create_dir_all(path.parent().unwrap()).map_err(|e| e.to_string())?;
+        fs::rename(&tmp_path, &path).map_err(|e| format!("Failed to store object: {}", e))
+    });
+    if result.is_err() {
+        fs::remove_file(&tmp_path).ok();
+    }
+    result
+}
+
+fn write_verified(path: &Path, oid: &str, size: u64, data: &mut impl Read) -> Result<(), String> {
+    let mut file = File::create(path).map_err(|e| format!("Failed to store object: {}", e))?;
+    let mut hasher = Sha256::new();
+    let mut written = 0;
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let read = match data.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.to_string()),
+        };
+        hasher.update(&buffer[..read]);
+        file.write_all(&buffer[..read])
+            .map_err(|e| format!("Failed to store object: {}", e))?;
+        written += read as u64;
+    }
+    if written != size {
+        return Err(format!("Expected {} bytes but received {}", size, written));
+    }
+    if hex::encode(hasher.finalize()) != oid {
+        return Err(String::from("Object does not match its ID"));
+    }
+    Ok(())
+}
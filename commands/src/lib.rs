@@ -1,16 +1,65 @@
 use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
 use regex::Regex;
-use std::fs::FileType;
+use std::fs::{File, FileType};
+use std::io::Read;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::process::CommandExt;
 use std::path::{Path, PathBuf};
-use std::{fs, iter};
-use users::get_current_username;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{env, fs, iter};
+use users::{get_current_uid, get_current_username};
 use walkdir::{IntoIter, WalkDir};
 
-pub const BASE_REPO_PATH: &str = "/srv/repos";
+pub mod ci;
+pub mod config;
+pub mod email;
+pub mod events;
+pub mod filter;
+pub mod hooks;
+pub mod issues;
+pub mod lfs;
+pub mod orgs;
+pub mod policies;
+pub mod signing;
+pub mod stats;
+pub mod status;
+pub mod transfers;
+pub mod webhooks;
+
+use config::config;
+use orgs::{parse_org, repo_org, ORG_PREFIX};
+
+pub const BASE_USER_PATH: &str = "/srv/users";
+
+/// Section of a bare repository's config holding options managed by the server
+pub const REPO_OPTION_SECTION: &str = "gitserver";
+
+/// Shared account all users connect as when running in gateway mode
+pub const GATEWAY_ACCOUNT: &str = "git";
+/// Environment variable the gateway uses to pass on the logical user
+pub const GATEWAY_USER_VAR: &str = "GIT_SERVER_USER";
+
+pub fn get_username() -> String {
+    let username = get_current_username()
+        .expect("Failed to get current username")
+        .into_string()
+        .expect("Username is not valid unicode");
+    if username == GATEWAY_ACCOUNT {
+        if let Ok(logical) = env::var(GATEWAY_USER_VAR) {
+            return logical;
+        }
+    }
+    username
+}
+
+/// Whether the process is running as the server itself rather than a user
+pub fn is_server() -> bool {
+    get_current_uid() == 0
+}
 
 pub fn get_repo_home() -> PathBuf {
-    let username = get_current_username().expect("Failed to get current username");
-    Path::new(BASE_REPO_PATH).join(username)
+    config().repo_path.join(get_username())
 }
 
 pub fn clean_empty_parent_folders(path: &Path, stop: Option<&Path>) {
@@ -53,6 +102,200 @@ pub fn enforce_git_suffix(path: PathBuf) -> Result<PathBuf, String> {
     }
 }
 
+pub fn is_bare_repo(path: &Path) -> bool {
+    path.join("HEAD").is_file() && path.join("objects").is_dir()
+}
+
+/// Find all bare repositories below a folder, relative to that folder
+pub fn bare_repos(root: &Path) -> Vec<PathBuf> {
+    let mut repos = Vec::new();
+    let mut it = WalkDir::new(root).min_depth(1).into_iter();
+    while let Some(entry) = it.next() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                eprintln!("Error traversing repos: {}", err);
+                std::process::exit(1)
+            }
+        };
+        if !entry.file_type().is_dir() {
+            continue;
+        }
+        if is_bare_repo(entry.path()) {
+            repos.push(entry.path().strip_prefix(root).unwrap().to_path_buf());
+            it.skip_current_dir();
+        }
+    }
+    repos
+}
+
+/// Bare repositories of every user, for jobs run by the server
+///
+/// Each repository is returned as its path prefixed with the owning user, and its
+/// location on disk.
+pub fn all_bare_repos() -> Vec<(PathBuf, PathBuf)> {
+    let config = config();
+    let users = match fs::read_dir(&config.repo_path) {
+        Ok(users) => users,
+        Err(err) => {
+            eprintln!("Error traversing repos: {}", err);
+            std::process::exit(1)
+        }
+    };
+
+    let mut repos = Vec::new();
+    for entry in users.filter_map(Result::ok) {
+        if !entry.file_type().is_ok_and(|t| t.is_dir()) {
+            continue;
+        }
+        let user_home = entry.path();
+        for path in bare_repos(&user_home) {
+            if !path.starts_with(&config.tmp_dir) {
+                let git_dir = user_home.join(&path);
+                repos.push((Path::new(&entry.file_name()).join(path), git_dir));
+            }
+        }
+    }
+    repos.sort();
+    repos
+}
+
+/// Create a git command operating on a bare repository
+///
+/// When run as root, the command runs as the owner of the repository so that
+/// any files it creates remain accessible to them.
+pub fn git_command(git_dir: &Path) -> Command {
+    let mut command = Command::new("git");
+    command.arg("--git-dir").arg(git_dir);
+    if is_server() {
+        if let Ok(metadata) = fs::metadata(git_dir) {
+            command.uid(metadata.uid()).gid(metadata.gid());
+        }
+    }
+    command
+}
+
+/// Run a git command on a bare repository, returning its output
+pub fn run_git(git_dir: &Path, args: &[&str]) -> Result<String, String> {
+    run_command(git_command(git_dir).args(args))
+}
+
+/// Run a git command creating commits on behalf of the current user, with the email
+/// address configured for them
+pub fn run_git_as_user(git_dir: &Path, args: &[&str]) -> Result<String, String> {
+    let username = get_username();
+    let email = config()
+        .email
+        .clone()
+        .unwrap_or_else(|| format!("{}@localhost", username));
+    let mut command = git_command(git_dir);
+    command
+        .env("GIT_AUTHOR_NAME", &username)
+        .env("GIT_AUTHOR_EMAIL", &email)
+        .env("GIT_COMMITTER_NAME", &username)
+        .env("GIT_COMMITTER_EMAIL", &email);
+    run_command(command.args(args))
+}
+
+fn run_command(command: &mut Command) -> Result<String, String> {
+    let output = command
+        .output()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(stderr.trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Record a server-managed option in the config of a bare repository
+pub fn set_repo_option(git_dir: &Path, key: &str, value: &str) -> Result<(), String> {
+    let key = format!("{}.{}", REPO_OPTION_SECTION, key);
+    run_git(git_dir, &["config", &key, value]).map(|_| ())
+}
+
+pub fn get_repo_option(git_dir: &Path, key: &str) -> Option<String> {
+    let key = format!("{}.{}", REPO_OPTION_SECTION, key);
+    run_git(git_dir, &["config", "--get", &key])
+        .ok()
+        .map(|value| value.trim().to_string())
+}
+
+pub fn get_repo_options(git_dir: &Path, key: &str) -> Vec<String> {
+    let key = format!("{}.{}", REPO_OPTION_SECTION, key);
+    run_git(git_dir, &["config", "--get-all", &key])
+        .map(|values| values.lines().map(String::from).collect())
+        .unwrap_or_default()
+}
+
+pub fn is_archived(git_dir: &Path) -> bool {
+    get_repo_option(git_dir, "archived").is_some_and(|value| value == "true")
+}
+
+/// Branch HEAD points to, which clones check out by default
+pub fn default_branch(git_dir: &Path) -> Option<String> {
+    run_git(git_dir, &["symbolic-ref", "--quiet", "--short", "HEAD"])
+        .ok()
+        .map(|branch| branch.trim().to_string())
+}
+
+/// Random hex string, used for secrets and delivery ids
+pub fn random_hex(bytes: usize) -> String {
+    let mut buffer = vec![0; bytes];
+    File::open("/dev/urandom")
+        .and_then(|mut f| f.read_exact(&mut buffer))
+        .expect("Failed to read random bytes");
+    hex::encode(buffer)
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Path of a bare repository relative to the user's repository folder, as they refer to it
+///
+/// Repositories of organisations are prefixed with the organisation, as in `org:team/app.git`.
+pub fn relative_repo_path(git_dir: &Path) -> Option<PathBuf> {
+    let git_dir = git_dir.canonicalize().ok()?;
+    let repo_home = get_repo_home().canonicalize().ok()?;
+    if let Ok(path) = git_dir.strip_prefix(repo_home) {
+        return Some(path.to_path_buf());
+    }
+    let repo_root = config().repo_path.canonicalize().ok()?;
+    let path = git_dir.strip_prefix(repo_root).ok()?;
+    repo_org(path).map(|_| path.to_path_buf())
+}
+
+/// Location of the bare repository a path in the user's home links to
+///
+/// Repositories of organisations are reached through the link to the organisation's folder.
+pub fn repo_git_dir(path: &Path) -> Result<PathBuf, String> {
+    let linked = match repo_org(path) {
+        Some(_) => is_bare_repo(path),
+        None => path.is_symlink(),
+    };
+    if !linked {
+        return Err(format!("No repository at '{}'", path.display()));
+    }
+    Ok(get_repo_home().join(path))
+}
+
+/// Expand the paths matching a glob set into the repositories they contain
+pub fn matching_repos(glob_set: &GlobSet) -> Vec<PathBuf> {
+    filter_repos(None, true, |path| glob_set.is_match(path))
+        .flat_map(|path| {
+            if represents_repo(&path) {
+                vec![path]
+            } else {
+                filter_repos(Some(path), false, |_| true).collect::<Vec<_>>()
+            }
+        })
+        .collect()
+}
+
 pub fn make_glob_set<'a>(globs: impl Iterator<Item = &'a Glob>) -> GlobSet {
     let mut builder = GlobSetBuilder::new();
     for glob in globs {
@@ -79,10 +322,15 @@ where
     )
 }
 
+/// Walks the repositories in the user's home, or a folder of it
+///
+/// Personal repositories are links, while the folders of organisations the user is
+/// a member of are linked as a whole and walked after the home itself.
 #[derive(Debug)]
 pub struct FilterRepos<I, P> {
     it: I,
     root: Option<PathBuf>,
+    org_roots: Vec<PathBuf>,
     match_folders: bool,
     predicate: P,
 }
@@ -91,19 +339,28 @@ impl<P> FilterRepos<IntoIter, P>
 where
     P: FnMut(&Path, FileType) -> (bool, bool),
 {
-    fn new(
-        root: Option<PathBuf>,
-        match_folders: bool,
-        predicate: P,
-    ) -> impl Iterator<Item = PathBuf> + Sized {
+    fn new(root: Option<PathBuf>, match_folders: bool, predicate: P) -> Self {
         let it_root = match &root {
             Some(root) => root,
             None => &PathBuf::from("."),
         };
         let it = WalkDir::new(it_root).into_iter();
+        let mut org_roots = Vec::new();
+        if root.is_none() {
+            if let Ok(entries) = fs::read_dir(".") {
+                org_roots = entries
+                    .filter_map(Result::ok)
+                    .filter(|e| e.file_type().is_ok_and(|t| t.is_symlink()))
+                    .filter(|e| e.file_name().to_string_lossy().starts_with(ORG_PREFIX))
+                    .map(|e| Path::new(".").join(e.file_name()))
+                    .collect();
+                org_roots.sort_by(|a, b| b.cmp(a));
+            }
+        }
         FilterRepos {
             it,
             root,
+            org_roots,
             match_folders,
             predicate,
         }
@@ -120,7 +377,13 @@ where
         loop {
             let next = self.it.next();
             let entry = match next {
-                None => return None,
+                None => match self.org_roots.pop() {
+                    Some(org_root) => {
+                        self.it = WalkDir::new(org_root).into_iter();
+                        continue;
+                    }
+                    None => return None,
+                },
                 Some(Ok(entry)) => entry,
                 Some(Err(err)) => {
                     eprintln!("Error traversing repos: {}", err);
@@ -129,22 +392,40 @@ where
             };
 
             if self.root.is_none() {
-                let components = entry.path().components();
-                let base_folder = components.skip(1).next();
+                let mut components = entry.path().components();
+                let base_folder = components.nth(1);
                 if base_folder.is_none() {
                     continue;
                 }
                 match base_folder.unwrap().as_os_str().to_str().unwrap() {
                     ".ssh" => continue,
                     "git-shell-commands" => continue,
+                    // Linked folders of organisations are walked once the home is done
+                    name if name.starts_with(ORG_PREFIX)
+                        && entry.depth() == 1
+                        && entry.path_is_symlink() =>
+                    {
+                        continue
+                    }
                     _ => (),
                 }
             }
 
-            let file_type = entry.file_type();
+            let file_type = match self.root.is_none() && entry.depth() == 0 {
+                // Folders of organisations are walked through their links
+                true => match fs::metadata(entry.path()) {
+                    Ok(metadata) => metadata.file_type(),
+                    Err(_) => continue,
+                },
+                false => entry.file_type(),
+            };
+            let is_repo_dir = file_type.is_dir() && is_bare_repo(entry.path());
+            if is_repo_dir {
+                self.it.skip_current_dir();
+            }
             match (
                 self.match_folders,
-                file_type.is_symlink(),
+                file_type.is_symlink() || is_repo_dir,
                 file_type.is_dir(),
             ) {
                 (_, true, _) => (),
@@ -157,8 +438,8 @@ where
                 false => entry.path().strip_prefix(".").unwrap(),
             };
 
-            let (matches, skip_dir) = (self.predicate)(&path, file_type);
-            if skip_dir {
+            let (matches, skip_dir) = (self.predicate)(path, file_type);
+            if skip_dir && !is_repo_dir {
                 self.it.skip_current_dir();
             }
             if matches {
@@ -179,30 +460,62 @@ pub fn parse_command(command: &str) -> Result<String, String> {
     }
 
     let re = Regex::new("^[a-z]+$").unwrap();
-    if !re.is_match(&command) {
+    if !re.is_match(command) {
         return Err(String::from("Invalid command"));
     }
     Ok(command.to_string())
 }
 
+pub fn parse_username(username: &str) -> Result<String, String> {
+    match username.len() {
+        0 => return Err(String::from("Username cannot be empty")),
+        33.. => return Err(String::from("Username cannot exceed 32 characters")),
+        _ => (),
+    }
+
+    let re = Regex::new("^[a-z_][a-z0-9_\\-]*$").unwrap();
+    if !re.is_match(username) {
+        return Err(String::from("Invalid username"));
+    }
+    Ok(username.to_string())
+}
+
 pub fn parse_repo_path(path: &str) -> Result<PathBuf, String> {
     match parse_repo_path_or_folder(path) {
         Err(e) => Err(e),
+        Ok(parsed) if repo_org(&parsed).is_some() && parsed.components().count() == 1 => {
+            Err(String::from("Organisation cannot be a repository"))
+        }
         Ok(parsed) => enforce_git_suffix(parsed),
     }
 }
 
 pub fn parse_repo_path_or_folder(path: &str) -> Result<PathBuf, String> {
-    match path.len() {
-        0 => return Err(String::from("Path cannot be empty")),
-        257.. => return Err(String::from("Path cannot exceed 256 characters")),
-        _ => (),
+    let config = config();
+    let limits = &config.limits;
+    if path.is_empty() {
+        return Err(String::from("Path cannot be empty"));
+    }
+    if path.len() > limits.max_path_length {
+        return Err(format!(
+            "Path cannot exceed {} characters",
+            limits.max_path_length
+        ));
     }
 
     if path == "." {
         return Ok(PathBuf::new());
     }
 
+    // Repositories of organisations live below a first component naming the organisation
+    let (org, rest) = match path.strip_prefix(ORG_PREFIX) {
+        Some(org_path) => match org_path.split_once('/') {
+            Some((org, rest)) => (Some(parse_org(org)?), rest),
+            None => (Some(parse_org(org_path)?), ""),
+        },
+        None => (None, path),
+    };
+
     let parsed = Path::new(path).to_owned();
     if parsed.is_absolute() {
         return Err(String::from("Absolute paths are not allowed"));
@@ -210,12 +523,20 @@ pub fn parse_repo_path_or_folder(path: &str) -> Result<PathBuf, String> {
     if parsed.components().any(|c| c.as_os_str().eq("..")) {
         return Err(String::from("Backtracking not allowed"));
     }
-    if parsed.components().count() > 4 {
-        return Err(String::from("Nesting must not exceed a depth of 4"));
+    if parsed.components().count() - usize::from(org.is_some()) > limits.max_depth {
+        return Err(format!(
+            "Nesting must not exceed a depth of {}",
+            limits.max_depth
+        ));
     }
 
-    let re = Regex::new("^(?:[A-Za-z0-9_\\-]+/)*[A-Za-z0-9_\\-]+(?:\\.git|/)?$").unwrap();
-    if !re.is_match(path) {
+    if (org.is_none() || !rest.is_empty()) && !config.path_regex().is_match(rest) {
+        if !config.has_default_name_pattern() {
+            return Err(format!(
+                "Repository and folder names must match '{}'",
+                limits.name_pattern
+            ));
+        }
         return Err(String::from(
             "Repository and folder names can only contain alphanumeric \
     characters, hyphens, and underscores",
@@ -228,12 +549,73 @@ pub fn parse_repo_path_or_folder(path: &str) -> Result<PathBuf, String> {
     Ok(parsed)
 }
 
-pub fn parse_repo_glob(glob: &str) -> Result<Glob, String> {
-    match glob.len() {
-        0 => return Err(String::from("Glob cannot be empty")),
-        65.. => return Err(String::from("Glob cannot exceed 64 characters")),
+pub fn parse_revision(revision: &str) -> Result<String, String> {
+    match revision.len() {
+        0 => return Err(String::from("Revision cannot be empty")),
+        257.. => return Err(String::from("Revision cannot exceed 256 characters")),
         _ => (),
     }
+    if revision.starts_with('-') {
+        return Err(String::from("Revision cannot start with '-'"));
+    }
+    if revision
+        .chars()
+        .any(|c| c.is_whitespace() || c.is_control())
+    {
+        return Err(String::from("Revision cannot contain whitespace"));
+    }
+    Ok(revision.to_string())
+}
+
+pub fn parse_branch(branch: &str) -> Result<String, String> {
+    let branch = parse_revision(branch)?;
+    let valid = Command::new("git")
+        .args(["check-ref-format", &format!("refs/heads/{}", branch)])
+        .status()
+        .is_ok_and(|status| status.success());
+    match valid {
+        true => Ok(branch),
+        false => Err(format!("'{}' is not a valid branch name", branch)),
+    }
+}
+
+/// Parse a path to a file or folder inside a repository
+pub fn parse_tree_path(path: &str) -> Result<PathBuf, String> {
+    if path.is_empty() {
+        return Err(String::from("Path cannot be empty"));
+    }
+    let parsed = Path::new(path);
+    if parsed.is_absolute() {
+        return Err(String::from("Absolute paths are not allowed"));
+    }
+    if parsed.components().any(|c| c.as_os_str().eq("..")) {
+        return Err(String::from("Backtracking not allowed"));
+    }
+    if path.starts_with('-') || path.chars().any(|c| c.is_control()) {
+        return Err(String::from("Invalid path"));
+    }
+    Ok(parsed.to_path_buf())
+}
+
+pub fn parse_repo_regex(pattern: &str) -> Result<Regex, String> {
+    let max_length = config().limits.max_glob_length;
+    if pattern.is_empty() {
+        return Err(String::from("Pattern cannot be empty"));
+    }
+    if pattern.len() > max_length {
+        return Err(format!("Pattern cannot exceed {} characters", max_length));
+    }
+    Regex::new(pattern).map_err(|e| e.to_string())
+}
+
+pub fn parse_repo_glob(glob: &str) -> Result<Glob, String> {
+    let max_length = config().limits.max_glob_length;
+    if glob.is_empty() {
+        return Err(String::from("Glob cannot be empty"));
+    }
+    if glob.len() > max_length {
+        return Err(format!("Glob cannot exceed {} characters", max_length));
+    }
 
     let path = Path::new(glob);
     if path.is_absolute() {
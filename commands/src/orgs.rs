@@ -0,0 +1,459 @@
+use crate::config::config;
+use crate::transfers::all_users;
+use crate::{get_username, is_server, parse_username, random_hex, BASE_USER_PATH, GATEWAY_ACCOUNT};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::os::unix;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::{fs, vec};
+use users::os::unix::GroupExt;
+
+/// Prefix of the first path component naming an organisation, as in `org:team/service.git`
+pub const ORG_PREFIX: &str = "org:";
+/// File in an organisation's folder listing its members
+pub const MEMBERS_FILE: &str = ".members.toml";
+/// File in a user's repository folder queueing their changes to members for the server
+pub const CHANGES_FILE: &str = ".member-changes.toml";
+/// Prefix of the unix group granting members file access when every user has an account
+const GROUP_PREFIX: &str = "org-";
+
+/// What a member of an organisation is allowed to do with its repositories
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Clone, fetch and open issues
+    Read,
+    /// Also push, create repositories and manage branches
+    Write,
+    /// Also delete and archive repositories and manage members
+    Owner,
+}
+
+impl Role {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::Read => "read",
+            Role::Write => "write",
+            Role::Owner => "owner",
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Members {
+    #[serde(default)]
+    pub members: BTreeMap<String, Role>,
+}
+
+impl Members {
+    pub fn owners(&self) -> usize {
+        self.members.values().filter(|r| **r == Role::Owner).count()
+    }
+}
+
+/// A change to the members of an organisation, which only the server can make
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MemberChange {
+    pub org: String,
+    pub user: String,
+    /// New role of the member, or none to remove them
+    pub role: Option<Role>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+struct MemberChanges {
+    change: Vec<MemberChange>,
+}
+
+pub fn parse_role(role: &str) -> Result<Role, String> {
+    match role {
+        "read" => Ok(Role::Read),
+        "write" => Ok(Role::Write),
+        "owner" => Ok(Role::Owner),
+        _ => Err(String::from("Role must be one of read, write or owner")),
+    }
+}
+
+/// Parse an organisation name, with or without its `org:` prefix
+pub fn parse_org(name: &str) -> Result<String, String> {
+    let name = name.strip_prefix(ORG_PREFIX).unwrap_or(name);
+    match name.len() {
+        0 => return Err(String::from("Organisation name cannot be empty")),
+        29.. => {
+            return Err(String::from(
+                "Organisation name cannot exceed 28 characters",
+            ))
+        }
+        _ => (),
+    }
+    let re = Regex::new("^[a-z][a-z0-9_\\-]*$").unwrap();
+    if !re.is_match(name) {
+        return Err(String::from("Invalid organisation name"));
+    }
+    Ok(name.to_string())
+}
+
+/// Organisation a repository path belongs to, if it is not a personal one
+pub fn repo_org(path: &Path) -> Option<String> {
+    match path.components().next()? {
+        Component::Normal(first) => first.to_str()?.strip_prefix(ORG_PREFIX).map(String::from),
+        _ => None,
+    }
+}
+
+pub fn org_folder_name(org: &str) -> String {
+    format!("{}{}", ORG_PREFIX, org)
+}
+
+/// Folder holding the bare repositories of an organisation
+pub fn org_dir(org: &str) -> PathBuf {
+    config().repo_path.join(org_folder_name(org))
+}
+
+/// Names of every organisation on the server
+pub fn all_orgs() -> Vec<String> {
+    let mut orgs = fs::read_dir(&config().repo_path)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+                .filter_map(|e| {
+                    let name = e.file_name().into_string().ok()?;
+                    name.strip_prefix(ORG_PREFIX).map(String::from)
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    orgs.sort();
+    orgs
+}
+
+/// Load the members of an organisation, which must have been written by the server
+pub fn load_members(org: &str) -> Result<Members, String> {
+    let dir = org_dir(org);
+    let path = dir.join(MEMBERS_FILE);
+    if fs::symlink_metadata(&path).is_ok_and(|m| m.uid() != 0 || !m.is_file()) {
+        return Err(format!(
+            "Members of '{}' were not written by the server",
+            org
+        ));
+    }
+    let raw = match fs::read_to_string(&path) {
+        Ok(raw) => raw,
+        Err(err) if err.kind() == io::ErrorKind::NotFound && dir.is_dir() => {
+            return Ok(Members::default())
+        }
+        Err(err)
+            if matches!(
+                err.kind(),
+                io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied
+            ) =>
+        {
+            return Err(format!("No organisation '{}'", org))
+        }
+        Err(err) => return Err(format!("Failed to read members: {}", err)),
+    };
+    toml::from_str(&raw).map_err(|e| format!("Invalid members in '{}': {}", path.display(), e))
+}
+
+/// Store the members of an organisation, readable by everyone but only changed by the server
+pub fn save_members(org: &str, members: &Members) -> Result<(), String> {
+    if !is_server() {
+        return Err(String::from("Members can only be changed by the server"));
+    }
+    let path = org_dir(org).join(MEMBERS_FILE);
+    let raw = toml::to_string(members).map_err(|e| e.to_string())?;
+    // Members can write to the folder, so never follow a link they left in place of the file
+    let temp = path.with_extension(random_hex(8));
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp)
+        .and_then(|mut file| file.write_all(raw.as_bytes()))
+        .map_err(|e| format!("Failed to save members: {}", e))?;
+    unix::fs::lchown(&temp, Some(0), Some(0)).map_err(|e| e.to_string())?;
+    fs::set_permissions(&temp, fs::Permissions::from_mode(0o644)).map_err(|e| e.to_string())?;
+    fs::rename(&temp, &path).map_err(|e| format!("Failed to save members: {}", e))
+}
+
+/// Change the members of an organisation on behalf of a user, returning what was done
+///
+/// Owners can change anyone's role, and every member can remove themselves.
+pub fn change_members(requester: &str, change: &MemberChange) -> Result<String, String> {
+    let org = parse_org(&change.org)?;
+    let name = org_folder_name(&org);
+    let mut members = load_members(&org)?;
+    let required = match (change.role, change.user == requester) {
+        (None, true) => Role::Read,
+        _ => Role::Owner,
+    };
+    match members.members.get(requester) {
+        Some(role) if *role >= required => (),
+        Some(_) => {
+            return Err(format!(
+                "Requires the {} role in '{}'",
+                required.name(),
+                name
+            ))
+        }
+        None => return Err(format!("Not a member of '{}'", name)),
+    }
+
+    let user = &change.user;
+    let (previous, done) = match change.role {
+        Some(role) => {
+            parse_username(user)?;
+            if !Path::new(BASE_USER_PATH).join(user).is_dir() {
+                return Err(format!("No user '{}'", user));
+            }
+            let previous = members.members.insert(user.to_string(), role);
+            let done = match previous {
+                Some(_) => format!("Changed role of '{}' to {}", user, role.name()),
+                None => format!("Added '{}' to '{}' as {}", user, name, role.name()),
+            };
+            (previous, done)
+        }
+        None => match members.members.remove(user) {
+            None => return Err(format!("'{}' is not a member of '{}'", user, name)),
+            previous => (previous, format!("Removed '{}' from '{}'", user, name)),
+        },
+    };
+    if previous == Some(Role::Owner) && members.owners() == 0 {
+        return Err(format!("'{}' is the last owner of '{}'", user, name));
+    }
+    save_members(&org, &members)?;
+    Ok(done)
+}
+
+fn changes_path(user: &str) -> PathBuf {
+    config().repo_path.join(user).join(CHANGES_FILE)
+}
+
+/// Queue a change to members for the server to make on behalf of the current user
+pub fn request_change(change: MemberChange) -> Result<(), String> {
+    let path = changes_path(&get_username());
+    let mut changes = match fs::read_to_string(&path) {
+        Ok(raw) => toml::from_str::<MemberChanges>(&raw)
+            .map_err(|e| format!("Invalid changes in '{}': {}", path.display(), e))?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => MemberChanges::default(),
+        Err(err) => return Err(format!("Failed to read changes: {}", err)),
+    };
+    changes.change.push(change);
+    let raw = toml::to_string(&changes).map_err(|e| e.to_string())?;
+    fs::write(&path, raw).map_err(|e| format!("Failed to request change: {}", e))
+}
+
+/// Make the changes to members every user has queued, if they are still allowed to
+fn apply_requested_changes() {
+    for user in all_users() {
+        let path = changes_path(&user);
+        let Ok(raw) = fs::read_to_string(&path) else {
+            continue;
+        };
+        if let Err(err) = fs::remove_file(&path) {
+            eprintln!("'{}' : Failed to remove changes: {}", user, err);
+            continue;
+        }
+        let changes = match toml::from_str::<MemberChanges>(&raw) {
+            Ok(changes) => changes,
+            Err(err) => {
+                eprintln!("'{}' : Invalid changes: {}", user, err);
+                continue;
+            }
+        };
+        for change in &changes.change {
+            match change_members(&user, change) {
+                Ok(done) => println!("'{}' : {}", user, done),
+                Err(err) => eprintln!("'{}' : {}", user, err),
+            }
+        }
+    }
+}
+
+/// Role of a user in an organisation, if they are a member
+pub fn role(org: &str, user: &str) -> Option<Role> {
+    load_members(org).ok()?.members.get(user).copied()
+}
+
+/// Organisations a user is a member of, with their role in each
+pub fn user_orgs(user: &str) -> Vec<(String, Role)> {
+    all_orgs()
+        .into_iter()
+        .filter_map(|org| {
+            let role = role(&org, user)?;
+            Some((org, role))
+        })
+        .collect()
+}
+
+/// Ensure the current user has at least a role in the organisation owning a repository
+///
+/// Personal repositories are always allowed, their owner can do anything with them.
+pub fn require_role(path: &Path, required: Role) -> Result<(), String> {
+    match repo_org(path) {
+        Some(org) => require_org_role(&org, required).map(|_| ()),
+        None => Ok(()),
+    }
+}
+
+/// Ensure the current user has at least a role in an organisation, returning its members
+pub fn require_org_role(org: &str, required: Role) -> Result<Members, String> {
+    let members = load_members(org)?;
+    match members.members.get(&get_username()) {
+        Some(role) if *role >= required => Ok(members),
+        Some(_) => Err(format!(
+            "Requires the {} role in '{}'",
+            required.name(),
+            org_folder_name(org)
+        )),
+        None => Err(format!("Not a member of '{}'", org_folder_name(org))),
+    }
+}
+
+/// Whether every user connects through the shared gateway account rather than their own
+pub fn is_gateway_mode() -> bool {
+    users::get_user_by_name(GATEWAY_ACCOUNT).is_some()
+}
+
+/// Create a new organisation with a single owner
+pub fn create_org(org: &str, owner: &str) -> Result<(), String> {
+    let dir = org_dir(org);
+    if dir.exists() {
+        return Err(format!("Organisation '{}' already exists", org));
+    }
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create organisation: {}", e))?;
+    let mut members = Members::default();
+    members.members.insert(owner.to_string(), Role::Owner);
+    save_members(org, &members)?;
+    sync_org(org)
+}
+
+/// Bring links and permissions in line with an organisation's members
+///
+/// Every member has a link to the organisation's folder in their home and their
+/// repository folder. Without a gateway, members additionally share a unix group
+/// owning the folder. Only the server can update the homes and groups of others.
+pub fn sync_org(org: &str) -> Result<(), String> {
+    let members = load_members(org)?;
+    if is_server() && !is_gateway_mode() {
+        sync_group(org, &members)?;
+    }
+    for user in members.members.keys() {
+        sync_links(user)?;
+    }
+    Ok(())
+}
+
+/// Apply requested changes to members, sync every organisation, and remove links of users
+/// who have left all of them
+pub fn sync_all() -> Result<(), String> {
+    if is_server() {
+        apply_requested_changes();
+    }
+    for org in all_orgs() {
+        if is_server() && !is_gateway_mode() {
+            sync_group(&org, &load_members(&org)?)?;
+        }
+    }
+    let users = fs::read_dir(BASE_USER_PATH).map_err(|e| e.to_string())?;
+    for entry in users.filter_map(Result::ok) {
+        if let Some(user) = entry.file_name().to_str() {
+            if parse_username(user).is_ok() {
+                sync_links(user)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Link the organisations a user is a member of into their homes, removing stale links
+pub fn sync_links(user: &str) -> Result<(), String> {
+    let orgs = user_orgs(user)
+        .into_iter()
+        .map(|(org, _)| org_folder_name(&org))
+        .collect::<Vec<_>>();
+    let homes = [
+        Path::new(BASE_USER_PATH).join(user),
+        config().repo_path.join(user),
+    ];
+    for home in homes.iter().filter(|home| home.is_dir()) {
+        for link in org_links(home) {
+            let name = link.file_name().unwrap().to_str().unwrap_or_default();
+            if !orgs.iter().any(|org| org == name) {
+                fs::remove_file(&link).map_err(|e| format!("Failed to unlink: {}", e))?;
+            }
+        }
+        for org in &orgs {
+            let link = home.join(org);
+            if !link.is_symlink() {
+                unix::fs::symlink(config().repo_path.join(org), &link)
+                    .map_err(|e| format!("Failed to link '{}': {}", link.display(), e))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Links to organisations in a home or repository folder
+fn org_links(home: &Path) -> vec::IntoIter<PathBuf> {
+    fs::read_dir(home)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter(|e| e.file_type().is_ok_and(|t| t.is_symlink()))
+                .filter(|e| e.file_name().to_string_lossy().starts_with(ORG_PREFIX))
+                .map(|e| e.path())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default()
+        .into_iter()
+}
+
+fn sync_group(org: &str, members: &Members) -> Result<(), String> {
+    let group = format!("{}{}", GROUP_PREFIX, org);
+    if users::get_group_by_name(&group).is_none() {
+        run_admin(Command::new("addgroup").args(["-S", &group]))?;
+    }
+    let current = users::get_group_by_name(&group)
+        .map(|g| {
+            g.members()
+                .iter()
+                .filter_map(|m| m.to_str().map(String::from))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    for user in current.iter().filter(|u| !members.members.contains_key(*u)) {
+        run_admin(Command::new("delgroup").args([user, &group]))?;
+    }
+    for user in members.members.keys().filter(|u| !current.contains(u)) {
+        if parse_username(user).is_ok() && users::get_user_by_name(user).is_some() {
+            run_admin(Command::new("addgroup").args([user, &group]))?;
+        }
+    }
+
+    let dir = org_dir(org);
+    run_admin(
+        Command::new("chown")
+            .arg(format!("root:{}", group))
+            .arg(&dir),
+    )?;
+    fs::set_permissions(&dir, fs::Permissions::from_mode(0o2770)).map_err(|e| e.to_string())
+}
+
+fn run_admin(command: &mut Command) -> Result<(), String> {
+    let output = command
+        .output()
+        .map_err(|e| format!("Failed to run {:?}: {}", command.get_program(), e))?;
+    match output.status.success() {
+        true => Ok(()),
+        false => Err(String::from_utf8_lossy(&output.stderr).trim().to_string()),
+    }
+}
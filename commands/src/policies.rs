@@ -0,0 +1,180 @@
+//! Rules for commit messages, authors and paths which pushes must follow
+//!
+//! Administrators set policies for any repository under `[[policy]]` in the configuration,
+//! which users cannot change. Each user can add their own in `.policies.toml` inside their
+//! repository folder, which only apply to their personal repositories. Each policy applies to
+//! the repositories matching a glob.
+
+use crate::config::config;
+use crate::orgs::repo_org;
+use crate::{get_repo_home, parse_repo_glob};
+use globset::Glob;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+pub const POLICIES_FILE: &str = ".policies.toml";
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    /// Repositories the policy applies to, as accepted by `parse_repo_glob`
+    pub glob: String,
+    /// Pattern every commit message must match, such as `^(feat|fix)(\(.+\))?: `
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_pattern: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_subject_length: Option<usize>,
+    /// Domains author and committer emails must belong to
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub email_domains: Vec<String>,
+    /// Globs of files commits cannot add or change
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub forbidden_paths: Vec<String>,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+struct PolicyFile {
+    policy: Vec<Policy>,
+}
+
+impl Policy {
+    pub fn matches(&self, path: &Path) -> bool {
+        match parse_repo_glob(&self.glob) {
+            Ok(glob) => {
+                let matcher = glob.compile_matcher();
+                path.ancestors().any(|p| matcher.is_match(p))
+            }
+            Err(_) => false,
+        }
+    }
+
+    pub fn allows_domain(&self, email: &str) -> bool {
+        let domain = email.rsplit_once('@').map_or("", |(_, domain)| domain);
+        self.email_domains.is_empty()
+            || self
+                .email_domains
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(domain))
+    }
+
+    pub fn is_forbidden(&self, file: &str) -> bool {
+        self.forbidden_paths.iter().any(|path| {
+            Glob::new(path).is_ok_and(|glob| {
+                let matcher = glob.compile_matcher();
+                Path::new(file).ancestors().any(|p| matcher.is_match(p))
+            })
+        })
+    }
+
+    /// Ensure every rule of a policy set in the configuration is valid
+    ///
+    /// Runs while the configuration loads, so the glob is only checked for its syntax.
+    pub fn validate(&self) -> Result<(), String> {
+        let invalid = |e: String| format!("Invalid policy for '{}': {}", self.glob, e);
+        Glob::new(&self.glob).map_err(|e| invalid(e.to_string()))?;
+        if let Some(pattern) = &self.message_pattern {
+            parse_message_pattern(pattern).map_err(invalid)?;
+        }
+        for domain in &self.email_domains {
+            parse_domain(domain).map_err(invalid)?;
+        }
+        for path in &self.forbidden_paths {
+            parse_path_glob(path).map_err(invalid)?;
+        }
+        match self.rules().is_empty() {
+            true => Err(invalid(String::from("A policy needs at least one rule"))),
+            false => Ok(()),
+        }
+    }
+
+    /// Short description of each rule, for listing
+    pub fn rules(&self) -> Vec<String> {
+        let mut rules = Vec::new();
+        if let Some(pattern) = &self.message_pattern {
+            rules.push(format!("message matches '{}'", pattern));
+        }
+        if let Some(length) = self.max_subject_length {
+            rules.push(format!("subject at most {} characters", length));
+        }
+        if !self.email_domains.is_empty() {
+            rules.push(format!("emails at {}", self.email_domains.join(", ")));
+        }
+        if !self.forbidden_paths.is_empty() {
+            rules.push(format!("no changes to {}", self.forbidden_paths.join(", ")));
+        }
+        rules
+    }
+}
+
+pub fn parse_message_pattern(pattern: &str) -> Result<String, String> {
+    Regex::new(pattern)
+        .map(|_| pattern.to_string())
+        .map_err(|e| format!("Invalid pattern: {}", e))
+}
+
+pub fn parse_domain(domain: &str) -> Result<String, String> {
+    let valid = !domain.is_empty()
+        && domain.len() <= 253
+        && domain
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+    match valid {
+        true => Ok(domain.to_ascii_lowercase()),
+        false => Err(format!("Invalid domain '{}'", domain)),
+    }
+}
+
+pub fn parse_path_glob(glob: &str) -> Result<String, String> {
+    Glob::new(glob)
+        .map(|_| glob.to_string())
+        .map_err(|e| format!("Invalid glob: {}", e))
+}
+
+fn policies_path() -> PathBuf {
+    get_repo_home().join(POLICIES_FILE)
+}
+
+pub fn load_policies() -> Result<Vec<Policy>, String> {
+    let path = policies_path();
+    let raw = match fs::read_to_string(&path) {
+        Ok(raw) => raw,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(format!("Failed to read policies: {}", err)),
+    };
+    toml::from_str::<PolicyFile>(&raw)
+        .map(|file| file.policy)
+        .map_err(|e| format!("Invalid policies in '{}': {}", path.display(), e))
+}
+
+/// Policies in effect for a repository, those of the server followed by the user's own
+///
+/// The user's own policies only apply to their personal repositories, so repositories of
+/// organisations are only subject to those of the server.
+pub fn repo_policies(path: &Path) -> Result<Vec<Policy>, String> {
+    let mut policies = config()
+        .policies
+        .iter()
+        .filter(|policy| policy.matches(path))
+        .cloned()
+        .collect::<Vec<_>>();
+    if repo_org(path).is_none() {
+        policies.extend(
+            load_policies()?
+                .into_iter()
+                .filter(|policy| policy.matches(path)),
+        );
+    }
+    Ok(policies)
+}
+
+pub fn save_policies(policies: &[Policy]) -> Result<(), String> {
+    let file = PolicyFile {
+        policy: policies.to_vec(),
+    };
+    let raw = toml::to_string(&file).map_err(|e| e.to_string())?;
+    fs::create_dir_all(get_repo_home()).map_err(|e| e.to_string())?;
+    fs::write(policies_path(), raw).map_err(|e| format!("Failed to save policies: {}", e))
+}
@@ -0,0 +1,183 @@
+//! Verifying signatures against the signing keys registered for a user
+//!
+//! Keys are kept in `.ssh/signing_keys` next to the user's `authorized_keys`, holding
+//! SSH public keys one per line and armored GPG public key blocks. As other users cannot read
+//! that folder, administrators also publish a copy of each user's keys in `.signers`.
+
+use crate::{git_command, parse_username, random_hex, BASE_USER_PATH};
+use std::fs::{self, DirBuilder};
+use std::io::Write;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+pub const SIGNING_KEYS_FILE: &str = ".ssh/signing_keys";
+/// Folder in the users' folder holding a readable copy of every user's signing keys
+pub const SIGNERS_FOLDER: &str = ".signers";
+/// Namespace git signs commits, tags and pushes in with SSH keys
+const SSH_NAMESPACE: &str = "git";
+
+pub fn signing_keys_path(username: &str) -> PathBuf {
+    Path::new(BASE_USER_PATH)
+        .join(username)
+        .join(SIGNING_KEYS_FILE)
+}
+
+/// Temporary keyrings holding the signing keys of one or more users
+pub struct Verifier {
+    username: String,
+    dir: PathBuf,
+    has_ssh_keys: bool,
+    has_gpg_keys: bool,
+}
+
+impl Verifier {
+    /// Keyrings with the keys of a single user, whose signatures `verify` checks
+    pub fn new(username: &str) -> Result<Verifier, String> {
+        let keys = fs::read_to_string(signing_keys_path(username)).unwrap_or_default();
+        Verifier::with_keys(username, &[(username.to_string(), keys)])
+    }
+
+    /// Keyrings with the published keys of every user, along with the current user's own
+    pub fn all_users(username: &str) -> Result<Verifier, String> {
+        let mut keys = fs::read_dir(Path::new(BASE_USER_PATH).join(SIGNERS_FOLDER))
+            .map(|entries| {
+                entries
+                    .filter_map(Result::ok)
+                    .filter_map(|e| e.file_name().into_string().ok())
+                    .filter(|user| parse_username(user).is_ok() && user != username)
+                    .map(|user| {
+                        let path = Path::new(BASE_USER_PATH).join(SIGNERS_FOLDER).join(&user);
+                        (user, fs::read_to_string(path).unwrap_or_default())
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let own = fs::read_to_string(signing_keys_path(username)).unwrap_or_default();
+        keys.push((username.to_string(), own));
+        Verifier::with_keys(username, &keys)
+    }
+
+    fn with_keys(username: &str, keys: &[(String, String)]) -> Result<Verifier, String> {
+        let dir = std::env::temp_dir().join(format!("signing-{}", random_hex(8)));
+        DirBuilder::new()
+            .mode(0o700)
+            .create(&dir)
+            .map_err(|e| format!("Failed to create keyring: {}", e))?;
+        let mut verifier = Verifier {
+            username: username.to_string(),
+            dir,
+            has_ssh_keys: false,
+            has_gpg_keys: false,
+        };
+
+        let mut allowed_signers = String::new();
+        let mut gpg_keys = String::new();
+        for (user, keys) in keys {
+            let mut in_block = false;
+            for line in keys.lines() {
+                let line = line.trim();
+                if line.starts_with("-----BEGIN PGP PUBLIC KEY BLOCK-----") {
+                    in_block = true;
+                }
+                if in_block {
+                    gpg_keys.push_str(line);
+                    gpg_keys.push('\n');
+                    in_block = !line.starts_with("-----END PGP PUBLIC KEY BLOCK-----");
+                } else if !line.is_empty() && !line.starts_with('#') {
+                    allowed_signers.push_str(&format!(
+                        "{} namespaces=\"{}\" {}\n",
+                        user, SSH_NAMESPACE, line
+                    ));
+                }
+            }
+        }
+
+        verifier.has_ssh_keys = !allowed_signers.is_empty();
+        fs::write(verifier.allowed_signers(), allowed_signers).map_err(|e| e.to_string())?;
+        if !gpg_keys.is_empty() {
+            let mut gpg = Command::new("gpg")
+                .args(["--batch", "--quiet", "--import"])
+                .env("GNUPGHOME", &verifier.dir)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .map_err(|e| format!("Failed to run gpg: {}", e))?;
+            gpg.stdin
+                .take()
+                .unwrap()
+                .write_all(gpg_keys.as_bytes())
+                .ok();
+            verifier.has_gpg_keys = gpg.wait().is_ok_and(|status| status.success());
+        }
+        Ok(verifier)
+    }
+
+    pub fn has_keys(&self) -> bool {
+        self.has_ssh_keys || self.has_gpg_keys
+    }
+
+    fn allowed_signers(&self) -> PathBuf {
+        self.dir.join("allowed_signers")
+    }
+
+    /// Whether a commit has a good signature by one of the keys
+    pub fn verify_commit(&self, git_dir: &Path, commit: &str) -> bool {
+        let allowed_signers = format!(
+            "gpg.ssh.allowedSignersFile={}",
+            self.allowed_signers().display()
+        );
+        git_command(git_dir)
+            .args(["-c", &allowed_signers, "verify-commit", commit])
+            .env("GNUPGHOME", &self.dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    }
+
+    /// Whether a signature over a payload was made with one of the keys of the user the
+    /// verifier was made for
+    pub fn verify(&self, payload: &str, signature: &str) -> bool {
+        let payload_path = self.dir.join("payload");
+        let signature_path = self.dir.join("payload.sig");
+        if fs::write(&payload_path, payload).is_err()
+            || fs::write(&signature_path, signature).is_err()
+        {
+            return false;
+        }
+
+        let mut command = if signature.starts_with("-----BEGIN SSH SIGNATURE-----") {
+            let mut command = Command::new("ssh-keygen");
+            command
+                .args(["-Y", "verify", "-n", SSH_NAMESPACE, "-I", &self.username])
+                .arg("-f")
+                .arg(self.allowed_signers())
+                .arg("-s")
+                .arg(&signature_path)
+                .stdin(fs::File::open(&payload_path).map_or(Stdio::null(), Stdio::from));
+            command
+        } else {
+            let mut command = Command::new("gpg");
+            command
+                .args(["--batch", "--verify"])
+                .arg(&signature_path)
+                .arg(&payload_path)
+                .env("GNUPGHOME", &self.dir)
+                .stdin(Stdio::null());
+            command
+        };
+        command
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    }
+}
+
+impl Drop for Verifier {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.dir).ok();
+    }
+}
@@ -0,0 +1,166 @@
+use crate::{lfs, run_git, unix_time};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// Disk usage and object counts of a bare repository
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RepoStats {
+    pub loose_objects: u64,
+    pub loose_size: u64,
+    pub packed_objects: u64,
+    pub packed_size: u64,
+    pub packs: u64,
+    pub lfs_objects: u64,
+    pub lfs_size: u64,
+}
+
+impl RepoStats {
+    pub fn objects(&self) -> u64 {
+        self.loose_objects + self.packed_objects
+    }
+
+    pub fn size(&self) -> u64 {
+        self.loose_size + self.packed_size + self.lfs_size
+    }
+
+    pub fn add(&mut self, other: &RepoStats) {
+        self.loose_objects += other.loose_objects;
+        self.loose_size += other.loose_size;
+        self.packed_objects += other.packed_objects;
+        self.packed_size += other.packed_size;
+        self.packs += other.packs;
+        self.lfs_objects += other.lfs_objects;
+        self.lfs_size += other.lfs_size;
+    }
+}
+
+pub fn repo_stats(git_dir: &Path) -> Result<RepoStats, String> {
+    let mut stats = RepoStats::default();
+    for line in run_git(git_dir, &["count-objects", "-v"])?.lines() {
+        let Some((key, value)) = line.split_once(": ") else {
+            continue;
+        };
+        let value = value.trim().parse::<u64>().unwrap_or(0);
+        match key {
+            "count" => stats.loose_objects = value,
+            "size" => stats.loose_size = value * 1024,
+            "in-pack" => stats.packed_objects = value,
+            "size-pack" => stats.packed_size = value * 1024,
+            "packs" => stats.packs = value,
+            _ => (),
+        }
+    }
+
+    (stats.lfs_objects, stats.lfs_size) = lfs::usage(git_dir);
+    Ok(stats)
+}
+
+/// Time of the most recent commit on any ref, or when the repository was created if it is empty
+pub fn last_updated(git_dir: &Path) -> Option<u64> {
+    let latest = run_git(
+        git_dir,
+        &[
+            "for-each-ref",
+            "--sort=-committerdate",
+            "--count=1",
+            "--format=%(committerdate:unix)",
+        ],
+    )
+    .ok()?;
+    match latest.trim().parse::<u64>() {
+        Ok(time) => Some(time),
+        Err(_) => git_dir
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs()),
+    }
+}
+
+pub fn is_empty(git_dir: &Path) -> bool {
+    run_git(git_dir, &["for-each-ref", "--count=1"]).is_ok_and(|refs| refs.trim().is_empty())
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Parse a size such as `512`, `10K`, `1.5M` or `2G` into bytes
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let split = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number = number
+        .parse::<f64>()
+        .map_err(|_| format!("Invalid size '{}'", size))?;
+    let multiplier: u64 = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        _ => return Err(format!("Invalid size unit '{}'", unit)),
+    };
+    let bytes = number * multiplier as f64;
+    // Casting saturates, so sizes beyond the largest u64 would silently become it
+    if bytes >= u64::MAX as f64 {
+        return Err(format!("Size '{}' is too large", size));
+    }
+    Ok(bytes as u64)
+}
+
+/// Parse a duration such as `90m`, `12h`, `30d`, `2w` or `1y` into seconds
+pub fn parse_duration(duration: &str) -> Result<u64, String> {
+    let split = duration
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(duration.len());
+    let (number, unit) = duration.split_at(split);
+    let number = number
+        .parse::<u64>()
+        .map_err(|_| format!("Invalid duration '{}'", duration))?;
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "" | "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        "y" => 365 * 24 * 60 * 60,
+        _ => return Err(format!("Invalid duration unit '{}'", unit)),
+    };
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("Duration '{}' is too long", duration))
+}
+
+/// Describe how long ago a unix time was, such as `5 minutes ago` or `3 days ago`
+pub fn format_age(time: u64) -> String {
+    const UNITS: [(&str, u64); 6] = [
+        ("year", 365 * 24 * 60 * 60),
+        ("week", 7 * 24 * 60 * 60),
+        ("day", 24 * 60 * 60),
+        ("hour", 60 * 60),
+        ("minute", 60),
+        ("second", 1),
+    ];
+    let age = unix_time().saturating_sub(time);
+    for (unit, seconds) in UNITS {
+        match age / seconds {
+            0 => continue,
+            1 => return format!("1 {} ago", unit),
+            n => return format!("{} {}s ago", n, unit),
+        }
+    }
+    String::from("just now")
+}
@@ -0,0 +1,158 @@
+//! Commit statuses reported by CI systems, stored as notes in `refs/notes/statuses`
+
+use crate::ci::STATUS_CONTEXT;
+use crate::{get_username, is_server, run_git, run_git_as_user, unix_time};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+pub const STATUS_NOTES_REF: &str = "refs/notes/statuses";
+/// Contexts only the server reports, as protected branches may require them
+pub const SERVER_CONTEXTS: &[&str] = &[STATUS_CONTEXT];
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatusState {
+    Success,
+    Failure,
+    Pending,
+}
+
+impl StatusState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            StatusState::Success => "success",
+            StatusState::Failure => "failure",
+            StatusState::Pending => "pending",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CommitStatus {
+    pub context: String,
+    pub state: StatusState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    pub user: String,
+    pub time: u64,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default)]
+struct StatusNote {
+    status: Vec<CommitStatus>,
+}
+
+pub fn parse_state(state: &str) -> Result<StatusState, String> {
+    match state {
+        "success" => Ok(StatusState::Success),
+        "failure" => Ok(StatusState::Failure),
+        "pending" => Ok(StatusState::Pending),
+        _ => Err(String::from(
+            "State must be 'success', 'failure' or 'pending'",
+        )),
+    }
+}
+
+pub fn parse_context(context: &str) -> Result<String, String> {
+    let valid = !context.is_empty()
+        && context.len() <= 64
+        && context
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-./".contains(c));
+    match valid {
+        true => Ok(context.to_string()),
+        false => Err(String::from(
+            "Contexts can only contain up to 64 letters, digits and '_-./'",
+        )),
+    }
+}
+
+/// Latest status of every context reported for a commit
+pub fn statuses(git_dir: &Path, commit: &str) -> Vec<CommitStatus> {
+    run_git(
+        git_dir,
+        &[
+            "notes",
+            &format!("--ref={}", STATUS_NOTES_REF),
+            "show",
+            commit,
+        ],
+    )
+    .ok()
+    .and_then(|raw| toml::from_str::<StatusNote>(&raw).ok())
+    .map(|note| note.status)
+    .unwrap_or_default()
+}
+
+/// Record the status of a context for a commit, replacing any earlier one
+pub fn set_status(
+    git_dir: &Path,
+    commit: &str,
+    context: &str,
+    state: StatusState,
+    url: Option<&str>,
+) -> Result<(), String> {
+    if SERVER_CONTEXTS.contains(&context) && !is_server() {
+        return Err(format!(
+            "'{}' is reported by the server and cannot be set",
+            context
+        ));
+    }
+    let mut note = StatusNote {
+        status: statuses(git_dir, commit),
+    };
+    note.status.retain(|status| status.context != context);
+    note.status.push(CommitStatus {
+        context: context.to_string(),
+        state,
+        url: url.map(String::from),
+        user: get_username(),
+        time: unix_time(),
+    });
+    note.status.sort_by(|a, b| a.context.cmp(&b.context));
+
+    let raw = toml::to_string(&note).map_err(|e| e.to_string())?;
+    run_git_as_user(
+        git_dir,
+        &[
+            "notes",
+            &format!("--ref={}", STATUS_NOTES_REF),
+            "add",
+            "--force",
+            "--message",
+            &raw,
+            commit,
+        ],
+    )
+    .map(|_| ())
+}
+
+/// Failure if any context failed, pending if any is pending, success if all succeeded
+pub fn combined_state(statuses: &[CommitStatus]) -> Option<StatusState> {
+    if statuses.is_empty() {
+        return None;
+    }
+    let has = |state| statuses.iter().any(|status| status.state == state);
+    if has(StatusState::Failure) {
+        Some(StatusState::Failure)
+    } else if has(StatusState::Pending) {
+        Some(StatusState::Pending)
+    } else {
+        Some(StatusState::Success)
+    }
+}
+
+/// Required contexts which have not succeeded for a commit
+pub fn missing_statuses(git_dir: &Path, commit: &str, required: &[String]) -> Vec<String> {
+    let statuses = statuses(git_dir, commit);
+    required
+        .iter()
+        .filter(|context| {
+            !statuses
+                .iter()
+                .any(|s| &s.context == *context && s.state == StatusState::Success)
+        })
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,235 @@
+//! Transfers of repositories between users
+//!
+//! A user offers repositories to another in `.transfers.toml` inside their repository folder,
+//! and the receiver accepts them in their own. As moving repositories between the trees of
+//! two users requires access to both, accepted transfers are performed by the server unless
+//! every user shares the gateway account.
+
+use crate::config::config;
+use crate::orgs::{is_gateway_mode, repo_org};
+use crate::{
+    clean_empty_parent_folders, is_bare_repo, is_server, parse_repo_path, parse_username,
+    unix_time, BASE_USER_PATH,
+};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, Metadata};
+use std::io;
+use std::os::unix;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+pub const TRANSFERS_FILE: &str = ".transfers.toml";
+
+/// A repository offered to another user
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Offer {
+    pub path: PathBuf,
+    pub to: String,
+    pub time: u64,
+}
+
+/// An offered repository the receiver has accepted, waiting to be moved
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Acceptance {
+    pub from: String,
+    pub path: PathBuf,
+    /// Path the repository will have in the receiver's home
+    pub destination: PathBuf,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Transfers {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub offer: Vec<Offer>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub accept: Vec<Acceptance>,
+}
+
+impl Offer {
+    pub fn new(path: &Path, to: &str) -> Offer {
+        Offer {
+            path: path.to_path_buf(),
+            to: to.to_string(),
+            time: unix_time(),
+        }
+    }
+}
+
+fn transfers_path(user: &str) -> PathBuf {
+    config().repo_path.join(user).join(TRANSFERS_FILE)
+}
+
+pub fn load_transfers(user: &str) -> Result<Transfers, String> {
+    let path = transfers_path(user);
+    let raw = match fs::read_to_string(&path) {
+        Ok(raw) => raw,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Transfers::default()),
+        Err(err) => return Err(format!("Failed to read transfers: {}", err)),
+    };
+    toml::from_str(&raw).map_err(|e| format!("Invalid transfers in '{}': {}", path.display(), e))
+}
+
+/// Store transfers, readable by everyone so receivers can find the offers made to them
+pub fn save_transfers(user: &str, transfers: &Transfers) -> Result<(), String> {
+    let path = transfers_path(user);
+    if transfers.offer.is_empty() && transfers.accept.is_empty() {
+        return match fs::remove_file(&path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                Err(format!("Failed to save transfers: {}", err))
+            }
+            _ => Ok(()),
+        };
+    }
+    let raw = toml::to_string(transfers).map_err(|e| e.to_string())?;
+    fs::write(&path, raw).map_err(|e| format!("Failed to save transfers: {}", e))
+}
+
+/// Users with a repository folder, excluding organisations
+pub fn all_users() -> Vec<String> {
+    let mut users = fs::read_dir(&config().repo_path)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+                .filter_map(|e| e.file_name().into_string().ok())
+                .filter(|name| parse_username(name).is_ok())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    users.sort();
+    users
+}
+
+/// Offers made to a user by everyone else, along with who made them
+pub fn offers_to(user: &str) -> Vec<(String, Offer)> {
+    all_users()
+        .into_iter()
+        .filter(|from| from != user)
+        .flat_map(|from| {
+            load_transfers(&from)
+                .map(|transfers| transfers.offer)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|offer| offer.to == user)
+                .map(move |offer| (from.clone(), offer))
+        })
+        .collect()
+}
+
+/// Whether the current process can move repositories between users itself
+pub fn can_transfer() -> bool {
+    is_server() || is_gateway_mode()
+}
+
+/// Move a repository from one user's tree into another's, relinking it in both homes
+///
+/// When run by the server, the repository and any folders created for it are handed to
+/// the receiver, as the owner of their repository folder and home. As the paths come from
+/// files users can write, they are checked again to stay within the trees of both users.
+pub fn transfer_repo(from: &str, path: &Path, to: &str, destination: &Path) -> Result<(), String> {
+    parse_username(from)?;
+    parse_username(to)?;
+    if from == to {
+        return Err(String::from(
+            "Cannot transfer repositories to the same user",
+        ));
+    }
+    check_transfer_path(path)?;
+    check_transfer_path(destination)?;
+    let repo_path = &config().repo_path;
+    let src_home = repo_path.join(from);
+    let dst_home = repo_path.join(to);
+    let src_git = src_home.join(path);
+    let dst_git = dst_home.join(destination);
+    let src_user_home = Path::new(BASE_USER_PATH).join(from);
+    let dst_user_home = Path::new(BASE_USER_PATH).join(to);
+    let src_link = src_user_home.join(path);
+    let dst_link = dst_user_home.join(destination);
+
+    if !is_bare_repo(&src_git) || src_git.is_symlink() {
+        return Err(format!("No repository at '{}'", path.display()));
+    }
+    check_within(&src_git, &src_home)?;
+    check_within(&dst_git, &dst_home)?;
+    // The links themselves point into the repository folders, so only check their folders
+    check_within(src_link.parent().unwrap(), &src_user_home)?;
+    check_within(dst_link.parent().unwrap(), &dst_user_home)?;
+    if dst_git.exists() || dst_link.exists() || dst_link.is_symlink() {
+        return Err(format!("'{}' is occupied", destination.display()));
+    }
+    let repo_owner = fs::metadata(&dst_home).map_err(|_| format!("No user '{}'", to))?;
+    let home_owner = fs::metadata(&dst_user_home).map_err(|_| format!("No user '{}'", to))?;
+
+    create_owned_folders(dst_git.parent().unwrap(), &repo_owner)?;
+    fs::rename(&src_git, &dst_git).map_err(|e| format!("Failed to move repository: {}", e))?;
+    if is_server() {
+        for entry in WalkDir::new(&dst_git).into_iter().filter_map(Result::ok) {
+            change_owner(entry.path(), &repo_owner)?;
+        }
+    }
+    clean_empty_parent_folders(&src_git, Some(&src_home));
+
+    if src_link.is_symlink() {
+        fs::remove_file(&src_link).map_err(|e| format!("Failed to unlink: {}", e))?;
+        clean_empty_parent_folders(&src_link, Some(&src_user_home));
+    }
+    create_owned_folders(dst_link.parent().unwrap(), &home_owner)?;
+    unix::fs::symlink(&dst_git, &dst_link).map_err(|e| format!("Failed to link: {}", e))?;
+    if is_server() {
+        change_owner(&dst_link, &home_owner)?;
+    }
+    Ok(())
+}
+
+/// Ensure a path is a plain repository path of a user, not one of an organisation
+fn check_transfer_path(path: &Path) -> Result<(), String> {
+    let parsed = path
+        .to_str()
+        .ok_or_else(|| String::from("Invalid path"))
+        .and_then(parse_repo_path)
+        .map_err(|err| format!("'{}' : {}", path.display(), err))?;
+    if parsed != path || repo_org(&parsed).is_some() {
+        return Err(format!("'{}' : Invalid repository path", path.display()));
+    }
+    Ok(())
+}
+
+/// Ensure a path, or the closest of its folders that exists, resolves to within a root
+fn check_within(path: &Path, root: &Path) -> Result<(), String> {
+    let outside = || format!("'{}' is outside of '{}'", path.display(), root.display());
+    let root = root.canonicalize().map_err(|_| outside())?;
+    let existing = path
+        .ancestors()
+        .find(|p| p.exists())
+        .and_then(|p| p.canonicalize().ok())
+        .ok_or_else(outside)?;
+    match existing.starts_with(&root) {
+        true => Ok(()),
+        false => Err(outside()),
+    }
+}
+
+/// Create a folder and its missing parents, owned by the owner of another file
+fn create_owned_folders(folder: &Path, owner: &Metadata) -> Result<(), String> {
+    let created = folder
+        .ancestors()
+        .take_while(|f| !f.exists())
+        .map(Path::to_path_buf)
+        .collect::<Vec<_>>();
+    fs::create_dir_all(folder).map_err(|e| format!("Failed to create folders: {}", e))?;
+    if is_server() {
+        for folder in &created {
+            change_owner(folder, owner)?;
+        }
+    }
+    Ok(())
+}
+
+fn change_owner(path: &Path, owner: &Metadata) -> Result<(), String> {
+    unix::fs::lchown(path, Some(owner.uid()), Some(owner.gid()))
+        .map_err(|e| format!("Failed to change owner of '{}': {}", path.display(), e))
+}
@@ -0,0 +1,436 @@
+//! Webhooks notifying other services of pushes and repository changes
+//!
+//! Each user registers webhooks in `.webhooks.toml` inside their repository folder, for the
+//! repositories matching a glob, including those of their organisations. Events are queued
+//! in `.webhooks.queue/` by whoever caused them and delivered by `webhooks --scheduled`, so
+//! unreachable webhooks never hold up a push. Payloads are JSON signed with the webhook's
+//! secret, and every delivery attempt is recorded in `.webhooks.log` next to it.
+
+use crate::config::config;
+use crate::hooks::RefUpdate;
+use crate::orgs::{load_members, repo_org, role, Role};
+use crate::transfers::all_users;
+use crate::{get_repo_home, get_username, is_server, parse_repo_glob, random_hex, unix_time};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::error;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::os::unix;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+pub const WEBHOOKS_FILE: &str = ".webhooks.toml";
+pub const DELIVERY_LOG: &str = ".webhooks.log";
+/// Folder in a user's repository folder holding the events they caused, until delivered
+pub const QUEUE_FOLDER: &str = ".webhooks.queue";
+/// Header holding the hex HMAC-SHA256 of the payload, prefixed with `sha256=`
+pub const SIGNATURE_HEADER: &str = "X-Signature-256";
+
+const ATTEMPTS: u32 = 3;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Webhook {
+    pub url: String,
+    /// Repositories to notify about, as accepted by `parse_repo_glob`
+    pub glob: String,
+    pub secret: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+struct WebhookFile {
+    webhook: Vec<Webhook>,
+}
+
+/// An event waiting to be delivered to the webhooks of everyone with the repository
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct QueuedEvent {
+    event: String,
+    payload: Value,
+}
+
+impl Webhook {
+    pub fn matches(&self, path: &Path) -> bool {
+        match parse_repo_glob(&self.glob) {
+            Ok(glob) => {
+                let matcher = glob.compile_matcher();
+                path.ancestors().any(|p| matcher.is_match(p))
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+/// Something that happened to a repository
+pub enum RepoEvent<'a> {
+    Push(&'a [RefUpdate]),
+    Create,
+    Move(&'a Path),
+    Delete,
+}
+
+impl RepoEvent<'_> {
+    fn name(&self) -> &'static str {
+        match self {
+            RepoEvent::Push(_) => "push",
+            RepoEvent::Create => "create",
+            RepoEvent::Move(_) => "move",
+            RepoEvent::Delete => "delete",
+        }
+    }
+
+    fn payload(&self, path: &Path) -> Value {
+        let mut payload = json!({
+            "event": self.name(),
+            "user": get_username(),
+            "repository": path.to_string_lossy(),
+            "timestamp": unix_time(),
+        });
+        match self {
+            RepoEvent::Push(updates) => {
+                payload["refs"] = updates
+                    .iter()
+                    .map(|update| {
+                        json!({
+                            "ref": update.name,
+                            "before": update.old,
+                            "after": update.new,
+                        })
+                    })
+                    .collect();
+            }
+            RepoEvent::Move(from) => payload["previous"] = json!(from.to_string_lossy()),
+            RepoEvent::Create | RepoEvent::Delete => (),
+        }
+        payload
+    }
+}
+
+fn webhooks_path(user: &str) -> PathBuf {
+    config().repo_path.join(user).join(WEBHOOKS_FILE)
+}
+
+fn delivery_log_path(user: &str) -> PathBuf {
+    config().repo_path.join(user).join(DELIVERY_LOG)
+}
+
+pub fn load_webhooks() -> Result<Vec<Webhook>, String> {
+    load_user_webhooks(&get_username())
+}
+
+fn load_user_webhooks(user: &str) -> Result<Vec<Webhook>, String> {
+    let path = webhooks_path(user);
+    let raw = match fs::read_to_string(&path) {
+        Ok(raw) => raw,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(format!("Failed to read webhooks: {}", err)),
+    };
+    toml::from_str::<WebhookFile>(&raw)
+        .map(|file| file.webhook)
+        .map_err(|e| format!("Invalid webhooks in '{}': {}", path.display(), e))
+}
+
+/// Store webhooks, readable only by their owner as they hold secrets
+pub fn save_webhooks(webhooks: &[Webhook]) -> Result<(), String> {
+    let file = WebhookFile {
+        webhook: webhooks.to_vec(),
+    };
+    let raw = toml::to_string(&file).map_err(|e| e.to_string())?;
+    fs::create_dir_all(get_repo_home()).map_err(|e| e.to_string())?;
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(webhooks_path(&get_username()))
+        .and_then(|mut f| f.write_all(raw.as_bytes()))
+        .map_err(|e| format!("Failed to save webhooks: {}", e))
+}
+
+pub fn parse_url(url: &str) -> Result<String, String> {
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err(String::from("URL must start with 'http://' or 'https://'"));
+    }
+    if url.len() > 2048 {
+        return Err(String::from("URL cannot exceed 2048 characters"));
+    }
+    if url.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err(String::from("URL cannot contain whitespace"));
+    }
+    Ok(url.to_string())
+}
+
+pub fn sign(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Queue an event for every webhook matching a repository, reporting failures to queue it
+pub fn notify(path: &Path, event: RepoEvent) {
+    let queued = QueuedEvent {
+        event: event.name().to_string(),
+        payload: event.payload(path),
+    };
+    let dir = get_repo_home().join(QUEUE_FOLDER);
+    let file = dir.join(format!("{}-{}.json", unix_time(), random_hex(4)));
+    let result = fs::create_dir_all(&dir)
+        .and_then(|_| fs::write(file, serde_json::to_string(&queued).unwrap()));
+    if let Err(err) = result {
+        eprintln!("Failed to queue webhooks: {}", err);
+    }
+}
+
+/// Users whose webhooks are notified of an event a user caused
+///
+/// Events of organisation repositories only count if their user could have caused them.
+fn recipients(user: &str, path: &Path) -> Vec<String> {
+    let Some(org) = repo_org(path) else {
+        return vec![user.to_string()];
+    };
+    if role(&org, user).is_none_or(|role| role < Role::Write) {
+        return Vec::new();
+    }
+    load_members(&org)
+        .map(|members| members.members.into_keys().collect())
+        .unwrap_or_default()
+}
+
+/// Deliver the events every user has queued, run by the server as it reads everyone's webhooks
+pub fn deliver_queued() {
+    for user in all_users() {
+        let dir = config().repo_path.join(&user).join(QUEUE_FOLDER);
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut files = entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .collect::<Vec<_>>();
+        files.sort();
+
+        for file in files {
+            let raw = fs::read_to_string(&file);
+            if let Err(err) = fs::remove_file(&file) {
+                eprintln!("'{}' : Failed to remove queued event: {}", user, err);
+                continue;
+            }
+            let Some(queued) = raw
+                .ok()
+                .and_then(|raw| serde_json::from_str::<QueuedEvent>(&raw).ok())
+            else {
+                eprintln!("'{}' : Invalid queued event '{}'", user, file.display());
+                continue;
+            };
+            let Some(path) = queued.payload["repository"].as_str().map(PathBuf::from) else {
+                continue;
+            };
+            for owner in recipients(&user, &path) {
+                let webhooks = match load_user_webhooks(&owner) {
+                    Ok(webhooks) => webhooks,
+                    Err(err) => {
+                        eprintln!("'{}' : {}", owner, err);
+                        continue;
+                    }
+                };
+                for webhook in webhooks.iter().filter(|w| w.matches(&path)) {
+                    if let Err(err) = deliver(&owner, webhook, &queued.event, &queued.payload) {
+                        eprintln!("'{}' : Webhook '{}' failed : {}", owner, webhook.url, err);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Deliver a payload to a user's webhook, retrying failed attempts, and return the final
+/// response status
+pub fn deliver(
+    owner: &str,
+    webhook: &Webhook,
+    event: &str,
+    payload: &Value,
+) -> Result<u16, String> {
+    let delivery = random_hex(8);
+    let body = payload.to_string();
+    let signature = sign(&webhook.secret, &body);
+    let repository = payload["repository"].as_str().unwrap_or("-");
+    let mut agent = ureq::AgentBuilder::new().timeout(TIMEOUT);
+    if !config().webhooks.allow_private {
+        agent = agent.resolver(resolve_public);
+    }
+    let agent = agent.build();
+
+    let mut result = Err(String::new());
+    for attempt in 1..=ATTEMPTS {
+        if attempt > 1 {
+            thread::sleep(Duration::from_secs(1 << (attempt - 2)));
+        }
+        result = match agent
+            .post(&webhook.url)
+            .set("Content-Type", "application/json")
+            .set("User-Agent", "git-server")
+            .set("X-Webhook-Event", event)
+            .set("X-Webhook-Delivery", &delivery)
+            .set(SIGNATURE_HEADER, &signature)
+            .send_string(&body)
+        {
+            Ok(response) => Ok(response.status()),
+            Err(ureq::Error::Status(status, _)) => Err(format!("HTTP {}", status)),
+            Err(ureq::Error::Transport(err)) => {
+                let mut message = err.kind().to_string();
+                if let Some(detail) = err.message() {
+                    message.push_str(&format!(": {}", detail));
+                }
+                if let Some(source) = error::Error::source(&err) {
+                    message.push_str(&format!(": {}", source));
+                }
+                Err(message)
+            }
+        };
+
+        let outcome = match &result {
+            Ok(status) => status.to_string(),
+            Err(err) => err.clone(),
+        };
+        log_delivery(
+            owner,
+            &[
+                &unix_time().to_string(),
+                &delivery,
+                event,
+                repository,
+                &webhook.url,
+                &attempt.to_string(),
+                &outcome,
+            ],
+        );
+
+        // Client errors won't succeed when retried
+        let retry = match &result {
+            Ok(_) => false,
+            Err(err) => !err.starts_with("HTTP 4"),
+        };
+        if !retry {
+            break;
+        }
+    }
+    result
+}
+
+/// Resolve the host of a webhook, refusing addresses of the server and private networks
+///
+/// Checking the addresses connected to, rather than the URL, also covers redirects and
+/// hosts resolving differently by the time of the delivery.
+fn resolve_public(netloc: &str) -> io::Result<Vec<SocketAddr>> {
+    let addrs = netloc.to_socket_addrs()?.collect::<Vec<_>>();
+    let public = addrs
+        .iter()
+        .copied()
+        .filter(|addr| is_public(addr.ip()))
+        .collect::<Vec<_>>();
+    if public.is_empty() && !addrs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("'{}' resolves to a private address", netloc),
+        ));
+    }
+    Ok(public)
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let shared = ip.octets()[0] == 100 && ip.octets()[1] & 0xc0 == 64;
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || shared)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local()
+                    || ip.is_multicast())
+            }
+        },
+    }
+}
+
+fn log_delivery(owner: &str, fields: &[&str]) {
+    let line = fields
+        .iter()
+        .map(|field| field.replace(['\t', '\n'], " "))
+        .collect::<Vec<_>>()
+        .join("\t");
+    let logged = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .mode(0o600)
+        .open(delivery_log_path(owner))
+        .and_then(|mut f| writeln!(f, "{}", line));
+    if let Err(err) = logged {
+        eprintln!("Failed to log webhook delivery: {}", err);
+    }
+    // Logs the server writes are still read by their owner
+    if is_server() {
+        if let Ok(home) = fs::metadata(config().repo_path.join(owner)) {
+            unix::fs::lchown(delivery_log_path(owner), Some(home.uid()), Some(home.gid())).ok();
+        }
+    }
+}
+
+/// A recorded delivery attempt
+pub struct Delivery {
+    pub time: u64,
+    pub id: String,
+    pub event: String,
+    pub repository: String,
+    pub url: String,
+    pub attempt: u32,
+    pub outcome: String,
+}
+
+impl Delivery {
+    pub fn succeeded(&self) -> bool {
+        self.outcome.starts_with('2')
+    }
+}
+
+/// Every recorded delivery attempt, oldest first
+pub fn read_delivery_log() -> Vec<Delivery> {
+    fs::read_to_string(delivery_log_path(&get_username()))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| {
+            let fields = line.split('\t').collect::<Vec<_>>();
+            let [time, id, event, repository, url, attempt, outcome] = fields[..] else {
+                return None;
+            };
+            Some(Delivery {
+                time: time.parse().ok()?,
+                id: id.to_string(),
+                event: event.to_string(),
+                repository: repository.to_string(),
+                url: url.to_string(),
+                attempt: attempt.parse().ok()?,
+                outcome: outcome.to_string(),
+            })
+        })
+        .collect()
+}
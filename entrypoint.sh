@@ -17,4 +17,33 @@ do
   ln -sf "/srv/users/$f" "/etc/$f"
 done
 
+mkdir -p /srv/users/.signers
+for keys in /srv/users/*/.ssh/signing_keys
+do
+  [ -s "$keys" ] || continue
+  name=$(basename "$(dirname "$(dirname "$keys")")")
+  cp "$keys" "/srv/users/.signers/$name"
+  chmod 644 "/srv/users/.signers/$name"
+done
+
+if [ "$GIT_SERVER_MODE" = "gateway" ]
+then
+  if ! id git > /dev/null 2>&1
+  then
+    adduser git -DH -h /var/lib/git -s /bin/sh
+    echo "git:*" | chpasswd
+  fi
+  chown -R git:git /srv/repos
+  # Members of organisations are only trusted when written by the server
+  find /srv/repos -mindepth 2 -maxdepth 2 -path '/srv/repos/org:*/.members.toml' \
+    -exec chown 0:0 {} + -exec chmod 644 {} +
+  find /srv/users -mindepth 1 -maxdepth 1 -type d -exec chown -R git:git {} +
+
+  sed -i /etc/ssh/sshd_config \
+    -e 's|#AuthorizedKeysCommand .\+|AuthorizedKeysCommand /srv/bin/gateway --authorized-keys %u|' \
+    -e 's|#AuthorizedKeysCommandUser .\+|AuthorizedKeysCommandUser git|'
+fi
+
+crond
+
 exec "$@"